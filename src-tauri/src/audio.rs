@@ -0,0 +1,325 @@
+use crate::error::TranscodeError;
+use crate::models::{
+    AudioBitDepth, AudioFormat, AudioLayout, AudioStream, DownmixCoefficients, ExternalAudio,
+    OutputPreset, SyncOffset, VideoStream,
+};
+use crate::preset::format_time_as_ffmpeg;
+use crate::timecode::parse_framerate;
+use std::path::Path;
+
+/// -3 dB as a linear gain
+const MINUS_3DB: f64 = 0.707;
+
+/// soxr precision in bits; 28 is its "very high quality" setting
+const SOXR_PRECISION: u32 = 28;
+
+/// Channel indices of the surrounds in SMPTE order
+/// (5.1: FL FR FC LFE BL BR, 7.1: FL FR FC LFE BL BR SL SR)
+const LEFT_SURROUNDS_51: &[u32] = &[4];
+const RIGHT_SURROUNDS_51: &[u32] = &[5];
+const LEFT_SURROUNDS_71: &[u32] = &[4, 6];
+const RIGHT_SURROUNDS_71: &[u32] = &[5, 7];
+
+impl AudioLayout {
+    /// Check the options, called when the job is submitted
+    pub fn validate(&self) -> Result<(), TranscodeError> {
+        match self {
+            AudioLayout::StereoDownmix(DownmixCoefficients::Custom { center, surround, lfe }) => {
+                for (name, gain) in [("centre", center), ("surround", surround), ("LFE", lfe)] {
+                    if !(0.0..=2.0).contains(gain) {
+                        return Err(TranscodeError::InvalidOption(format!(
+                            "{} downmix gain {} must be between 0 and 2",
+                            name, gain
+                        )));
+                    }
+                }
+                Ok(())
+            }
+            AudioLayout::Custom(routes) => {
+                if routes.is_empty() {
+                    return Err(TranscodeError::InvalidOption(
+                        "custom audio routing has no output tracks".to_string(),
+                    ));
+                }
+                if let Some(index) = routes.iter().position(|route| route.is_empty()) {
+                    return Err(TranscodeError::InvalidOption(format!(
+                        "custom audio routing track {} has no channels",
+                        index
+                    )));
+                }
+                Ok(())
+            }
+            _ => Ok(()),
+        }
+    }
+
+    /// Check the layout against the total channel count of the selected tracks,
+    /// called when the job starts
+    pub fn check_channels(&self, channels: u32) -> Result<(), TranscodeError> {
+        match self {
+            AudioLayout::StereoDownmix(_) if !matches!(channels, 1 | 2 | 6 | 8) => {
+                Err(TranscodeError::InvalidOption(format!(
+                    "stereo downmix needs a mono, stereo, 5.1 or 7.1 source, not {} channels",
+                    channels
+                )))
+            }
+            AudioLayout::Custom(routes) => {
+                match routes.iter().flatten().find(|channel| **channel >= channels) {
+                    Some(channel) => Err(TranscodeError::InvalidOption(format!(
+                        "audio routing uses channel {} but the source has {} channels",
+                        channel, channels
+                    ))),
+                    None => Ok(()),
+                }
+            }
+            _ => Ok(()),
+        }
+    }
+
+    /// Number of output tracks for a source with `channels` channels
+    pub fn output_tracks(&self, channels: u32) -> usize {
+        match self {
+            AudioLayout::SplitMono => channels as usize,
+            AudioLayout::Merge | AudioLayout::StereoDownmix(_) => 1,
+            AudioLayout::Custom(routes) => routes.len(),
+        }
+    }
+
+    /// pan filter producing each output track from the merged source channels,
+    /// None where the source passes through unchanged
+    pub fn output_filters(&self, channels: u32) -> Vec<Option<String>> {
+        match self {
+            AudioLayout::SplitMono => (0..channels)
+                .map(|channel| Some(format!("pan=mono|c0=c{}", channel)))
+                .collect(),
+            AudioLayout::Merge => vec![None],
+            AudioLayout::StereoDownmix(coefficients) => vec![coefficients.pan_filter(channels)],
+            AudioLayout::Custom(routes) => routes
+                .iter()
+                .map(|route| {
+                    let layout = match route.len() {
+                        1 => "mono".to_string(),
+                        2 => "stereo".to_string(),
+                        n => format!("{}c", n),
+                    };
+                    let gains: Vec<String> = route
+                        .iter()
+                        .enumerate()
+                        .map(|(output, source)| format!("c{}=c{}", output, source))
+                        .collect();
+                    Some(format!("pan={}|{}", layout, gains.join("|")))
+                })
+                .collect(),
+        }
+    }
+}
+
+impl DownmixCoefficients {
+    /// Stereo pan filter for a source with `channels` channels, None for stereo sources
+    fn pan_filter(&self, channels: u32) -> Option<String> {
+        let (left_surrounds, right_surrounds) = match channels {
+            1 => return Some("pan=stereo|c0=c0|c1=c0".to_string()),
+            2 => return None,
+            6 => (LEFT_SURROUNDS_51, RIGHT_SURROUNDS_51),
+            _ => (LEFT_SURROUNDS_71, RIGHT_SURROUNDS_71),
+        };
+
+        // Gains for (centre, LFE, left surrounds, right surrounds) into each side
+        let (center, lfe, left, right) = match *self {
+            DownmixCoefficients::Itu => (
+                MINUS_3DB,
+                0.0,
+                (MINUS_3DB, 0.0),
+                (0.0, MINUS_3DB),
+            ),
+            // The surrounds are phase-shifted into both sides so a decoder can steer them back
+            DownmixCoefficients::LtRt => (MINUS_3DB, 0.0, (-0.871, -0.489), (0.489, 0.871)),
+            DownmixCoefficients::Custom { center, surround, lfe } => {
+                (center, lfe, (surround, 0.0), (0.0, surround))
+            }
+        };
+
+        let side = |front: u32, (from_left, from_right): (f64, f64)| {
+            let mut terms = vec![(1.0, front), (center, 2), (lfe, 3)];
+            terms.extend(left_surrounds.iter().map(|channel| (from_left, *channel)));
+            terms.extend(right_surrounds.iter().map(|channel| (from_right, *channel)));
+            mix_expr(&terms)
+        };
+
+        Some(format!("pan=stereo|c0={}|c1={}", side(0, left), side(1, right)))
+    }
+}
+
+impl AudioFormat {
+    /// Check the format against what the preset's codec can write, called when the job is submitted
+    pub fn validate(&self, preset: OutputPreset) -> Result<(), TranscodeError> {
+        if let Some(rate) = self.sample_rate {
+            let max_rate = if preset.encodes_aac() { 96000 } else { 192000 };
+            if !(8000..=max_rate).contains(&rate) {
+                return Err(TranscodeError::InvalidOption(format!(
+                    "sample rate {} must be between 8000 and {} Hz",
+                    rate, max_rate
+                )));
+            }
+        }
+        match self.bit_depth {
+            Some(_) if preset.encodes_aac() => Err(TranscodeError::InvalidOption(
+                "AAC presets have no output bit depth".to_string(),
+            )),
+            Some(AudioBitDepth::Float32) if preset == OutputPreset::Flac => Err(
+                TranscodeError::InvalidOption("FLAC cannot store 32-bit float".to_string()),
+            ),
+            _ => Ok(()),
+        }
+    }
+}
+
+impl AudioBitDepth {
+    /// PCM codec writing this depth
+    pub fn pcm_codec(self) -> &'static str {
+        match self {
+            AudioBitDepth::Int16 => "pcm_s16le",
+            AudioBitDepth::Int24 => "pcm_s24le",
+            AudioBitDepth::Float32 => "pcm_f32le",
+        }
+    }
+
+    /// Sample format the encoders take; 24-bit travels in 32-bit samples
+    fn sample_fmt(self) -> &'static str {
+        match self {
+            AudioBitDepth::Int16 => "s16",
+            AudioBitDepth::Int24 => "s32",
+            AudioBitDepth::Float32 => "flt",
+        }
+    }
+
+    /// Whether writing `source` at this depth loses resolution, which needs dither.
+    /// Float sources (including every lossy codec) always do when written as integers.
+    fn reduces(self, source: &AudioStream) -> bool {
+        let bits = match self {
+            AudioBitDepth::Int16 => 16,
+            AudioBitDepth::Int24 => 24,
+            AudioBitDepth::Float32 => return false,
+        };
+        let source_is_float =
+            source.sample_fmt.starts_with("flt") || source.sample_fmt.starts_with("dbl");
+        source_is_float || bits < source.bit_depth
+    }
+}
+
+/// Sample rate and bit depth conversion of `source` with the soxr resampler,
+/// dithering when the bit depth drops. `resampled` forces the filter when an
+/// earlier filter changed the rate (loudnorm runs at 192 kHz).
+/// None when the source already matches.
+pub fn format_filter(
+    source: &AudioStream,
+    sample_rate: Option<u32>,
+    bit_depth: Option<AudioBitDepth>,
+    resampled: bool,
+) -> Option<String> {
+    let rate = sample_rate.unwrap_or(source.sample_rate);
+    let dither = bit_depth.is_some_and(|depth| depth.reduces(source));
+    if rate == source.sample_rate && !resampled && !dither {
+        return None;
+    }
+
+    let mut filter = format!("aresample=osr={}:resampler=soxr:precision={}", rate, SOXR_PRECISION);
+    if let Some(depth) = bit_depth {
+        filter.push_str(&format!(":osf={}", depth.sample_fmt()));
+        if dither {
+            filter.push_str(":dither_method=triangular");
+        }
+    }
+    Some(filter)
+}
+
+impl ExternalAudio {
+    /// Check the options, called when the job is submitted
+    pub fn validate(&self) -> Result<(), TranscodeError> {
+        if !Path::new(&self.path).is_file() {
+            return Err(TranscodeError::InvalidOption(format!(
+                "external audio not found: {}",
+                self.path
+            )));
+        }
+        if let Some(SyncOffset::Seconds(seconds)) = self.offset {
+            if !seconds.is_finite() {
+                return Err(TranscodeError::InvalidOption(format!(
+                    "invalid external audio offset: {}",
+                    seconds
+                )));
+            }
+        }
+        Ok(())
+    }
+
+    /// Convert a frame offset to seconds at the source frame rate, called when the job starts
+    pub fn resolve_offset(&mut self, video: Option<&VideoStream>) -> Result<(), TranscodeError> {
+        if let Some(SyncOffset::Frames(frames)) = self.offset {
+            let fps = video
+                .and_then(|video| parse_framerate(&video.framerate))
+                .ok_or_else(|| {
+                    TranscodeError::InvalidOption(
+                        "an external audio offset in frames needs a video source".to_string(),
+                    )
+                })?;
+            self.offset = Some(SyncOffset::Seconds(frames as f64 / fps));
+        }
+        Ok(())
+    }
+
+    /// Offset in seconds; frame offsets are resolved before the arguments are built
+    pub fn offset_seconds(&self) -> f64 {
+        match self.offset {
+            Some(SyncOffset::Seconds(seconds)) => seconds,
+            _ => 0.0,
+        }
+    }
+}
+
+/// Input arguments for audio starting `offset` seconds into the source timeline,
+/// seeked to `output_start`, where the output starts on that timeline.
+/// Seeking this input on its own lets the audio shift before the segment trims it.
+pub fn synced_input_args(path: &str, offset: f64, output_start: f64) -> Vec<String> {
+    let mut args = Vec::new();
+    let seek = output_start - offset;
+    if seek > 0.0 {
+        args.push("-ss".to_string());
+        args.push(format_time_as_ffmpeg(seek));
+    }
+    args.push("-i".to_string());
+    args.push(path.to_string());
+    args
+}
+
+/// Filters lining up an input from `synced_input_args` with the output: delay it
+/// when it starts after the output does, then pad or cut it to the output `duration`.
+/// Nothing is stretched, so the audio keeps its pitch and length.
+pub fn sync_filters(offset: f64, output_start: f64, duration: f64) -> Vec<String> {
+    let mut filters = Vec::new();
+    let delay = offset - output_start;
+    if delay > 0.0 {
+        filters.push(format!("adelay=delays={}:all=1", (delay * 1000.0).round() as u64));
+    }
+    filters.push("apad".to_string());
+    filters.push(format!("atrim=end={:.6}", duration.max(0.0)));
+    filters
+}
+
+/// pan channel expression such as "c0+0.707*c2-0.871*c4", skipping zero gains
+fn mix_expr(terms: &[(f64, u32)]) -> String {
+    let mut expr = String::new();
+    for (gain, channel) in terms.iter().filter(|(gain, _)| *gain != 0.0) {
+        if *gain < 0.0 {
+            expr.push('-');
+        } else if !expr.is_empty() {
+            expr.push('+');
+        }
+        if (gain.abs() - 1.0).abs() < f64::EPSILON {
+            expr.push_str(&format!("c{}", channel));
+        } else {
+            expr.push_str(&format!("{}*c{}", gain.abs(), channel));
+        }
+    }
+    expr
+}
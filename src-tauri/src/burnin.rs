@@ -0,0 +1,167 @@
+use crate::error::TranscodeError;
+use crate::models::{BurnIn, BurnInContent, BurnInPosition, MediaMetadata};
+use crate::preset::quote_filter_value;
+use crate::timecode::{parse_framerate, Timecode};
+use std::path::Path;
+
+/// Default text height as a fraction of the frame height
+const DEFAULT_SIZE: f64 = 0.035;
+const DEFAULT_BOX_OPACITY: f64 = 0.5;
+
+/// Monospace fonts tried when no font file is given, before falling back to fontconfig
+const SYSTEM_FONTS: &[&str] = &[
+    "C:/Windows/Fonts/consola.ttf",
+    "/System/Library/Fonts/Menlo.ttc",
+    "/usr/share/fonts/truetype/dejavu/DejaVuSansMono.ttf",
+];
+
+impl BurnIn {
+    /// Check the options, called when the job is submitted
+    pub fn validate(&self) -> Result<(), TranscodeError> {
+        if self.elements.is_empty() {
+            return Err(TranscodeError::InvalidOption("burn-in has no elements".to_string()));
+        }
+        if let Some(size) = self.size {
+            if !(size > 0.0 && size <= 0.5) {
+                return Err(TranscodeError::InvalidOption(format!(
+                    "burn-in size {} must be between 0 and 0.5 of the frame height",
+                    size
+                )));
+            }
+        }
+        if let Some(opacity) = self.box_opacity {
+            if !(0.0..=1.0).contains(&opacity) {
+                return Err(TranscodeError::InvalidOption(format!(
+                    "burn-in box opacity {} must be between 0 and 1",
+                    opacity
+                )));
+            }
+        }
+        if let Some(font) = &self.font_file {
+            if !Path::new(font).is_file() {
+                return Err(TranscodeError::InvalidOption(format!("font file not found: {}", font)));
+            }
+        }
+        Ok(())
+    }
+
+    /// Build one drawtext filter per element.
+    /// `framerate` is the output rate and `segment_start` is where the output
+    /// starts in the source, in seconds.
+    pub fn filters(&self, metadata: &MediaMetadata, framerate: &str, segment_start: f64) -> Vec<String> {
+        let Some(video) = &metadata.video else {
+            return Vec::new();
+        };
+        let framerate = if parse_framerate(framerate).is_some() {
+            framerate
+        } else {
+            "25/1"
+        };
+        let fps = parse_framerate(framerate).unwrap_or(25.0);
+        let start_frame = (segment_start * fps).round() as u64;
+
+        let text_height = video.display_height as f64 * self.size.unwrap_or(DEFAULT_SIZE);
+        let font_size = (text_height.round() as u32).max(8);
+        let line_height = font_size * 3 / 2;
+        let box_opacity = self.box_opacity.unwrap_or(DEFAULT_BOX_OPACITY);
+        let box_style = if box_opacity > 0.0 {
+            format!(":box=1:boxcolor=black@{:.2}:boxborderw={}", box_opacity, font_size / 4)
+        } else {
+            String::new()
+        };
+        let font = self.font_option();
+        let file_name = escape_drawtext(
+            &Path::new(&metadata.file_path)
+                .file_name()
+                .map(|name| name.to_string_lossy().to_string())
+                .unwrap_or_default(),
+        );
+
+        // Elements sharing a position are stacked away from the frame edge
+        let mut stacked = [0u32; 6];
+        let mut filters = Vec::new();
+
+        for element in &self.elements {
+            let slot = &mut stacked[element.position as usize];
+            let (x, y) = position_expr(element.position, font_size, *slot * line_height);
+            *slot += 1;
+
+            let content = match &element.content {
+                BurnInContent::Timecode => {
+                    let timecode = metadata
+                        .timecode
+                        .as_deref()
+                        .and_then(Timecode::parse)
+                        .unwrap_or(Timecode::from_frames(0, 25, false))
+                        .offset_by_seconds(segment_start, framerate);
+                    format!(
+                        "timecode={}:rate={}",
+                        quote_filter_value(&timecode.to_string()),
+                        framerate
+                    )
+                }
+                BurnInContent::Filename => format!("text={}", quote_filter_value(&file_name)),
+                BurnInContent::FrameCounter => format!(
+                    "text={}:start_number={}",
+                    quote_filter_value("%{frame_num}"),
+                    start_frame
+                ),
+                BurnInContent::Text(text) => {
+                    let text = escape_drawtext(text)
+                        .replace("{frame}", "%{frame_num}")
+                        .replace("{time}", &format!("%{{pts:hms:{}}}", segment_start))
+                        .replace("{filename}", &file_name);
+                    format!("text={}:start_number={}", quote_filter_value(&text), start_frame)
+                }
+            };
+
+            filters.push(format!(
+                "drawtext={}:{}:fontsize={}:fontcolor=white:x={}:y={}{}",
+                font, content, font_size, x, y, box_style
+            ));
+        }
+
+        filters
+    }
+
+    /// drawtext font option: the requested file, a known system font, or fontconfig
+    fn font_option(&self) -> String {
+        let font_file = self
+            .font_file
+            .clone()
+            .or_else(|| {
+                SYSTEM_FONTS
+                    .iter()
+                    .find(|path| Path::new(path).is_file())
+                    .map(|path| path.to_string())
+            });
+
+        match font_file {
+            Some(path) => format!("fontfile={}", quote_filter_value(&path)),
+            None => "font=monospace".to_string(),
+        }
+    }
+}
+
+/// x/y expressions for a position, `offset` pixels away from the nearest edge
+fn position_expr(position: BurnInPosition, margin: u32, offset: u32) -> (String, String) {
+    let left = format!("{}", margin);
+    let center = "(w-tw)/2".to_string();
+    let right = format!("w-tw-{}", margin);
+    let top = format!("{}", margin + offset);
+    let bottom = format!("h-th-{}", margin + offset);
+
+    match position {
+        BurnInPosition::TopLeft => (left, top),
+        BurnInPosition::TopCenter => (center, top),
+        BurnInPosition::TopRight => (right, top),
+        BurnInPosition::BottomLeft => (left, bottom),
+        BurnInPosition::BottomCenter => (center, bottom),
+        BurnInPosition::BottomRight => (right, bottom),
+    }
+}
+
+/// Escape literal text for drawtext's own expansion ('\' and '%' are special)
+fn escape_drawtext(text: &str) -> String {
+    text.replace('\\', "\\\\").replace('%', "\\%")
+}
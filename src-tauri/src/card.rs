@@ -0,0 +1,349 @@
+use crate::error::TranscodeError;
+use crate::ffmpeg::{concat, ffprobe};
+use crate::models::{CardClip, CardLayout, CardScan, MediaMetadata};
+use crate::timecode::{FrameRate, Timecode};
+use std::collections::BTreeMap;
+use std::path::{Path, PathBuf};
+
+/// Cameras writing to FAT32 cards split recordings at the 2 GB or 4 GB file
+/// limit, so only a file that came close to one can continue in the next.
+/// Used for layouts whose files say nothing of the recording they belong to.
+const SPAN_MIN_BYTES: u64 = 1_900_000_000;
+
+/// A clip file with its size in bytes and probed metadata
+type CardFile = (PathBuf, u64, MediaMetadata);
+
+impl CardLayout {
+    const ALL: [CardLayout; 5] = [
+        CardLayout::Avchd,
+        CardLayout::Xdcam,
+        CardLayout::P2,
+        CardLayout::GoPro,
+        CardLayout::Dji,
+    ];
+
+    /// Folders holding the clips, relative to the card root. Matched without
+    /// regard to case; `*` matches the rest of a folder name.
+    fn folder_patterns(self) -> &'static [&'static str] {
+        match self {
+            CardLayout::Avchd => &["PRIVATE/AVCHD/BDMV/STREAM", "AVCHD/BDMV/STREAM", "BDMV/STREAM"],
+            CardLayout::Xdcam => &["Clip", "XDROOT/Clip", "BPAV/CLPR/*"],
+            CardLayout::P2 => &["CONTENTS/VIDEO"],
+            CardLayout::GoPro => &["DCIM/*GOPRO"],
+            CardLayout::Dji => &["DCIM/*MEDIA", "DCIM/DJI_*"],
+        }
+    }
+
+    /// Clip file extensions, lower case. Proxies and thumbnails are left out.
+    fn extensions(self) -> &'static [&'static str] {
+        match self {
+            CardLayout::Avchd => &["mts", "m2ts"],
+            CardLayout::Xdcam => &["mxf", "mp4"],
+            CardLayout::P2 => &["mxf"],
+            CardLayout::GoPro => &["mp4"],
+            CardLayout::Dji => &["mp4", "mov"],
+        }
+    }
+
+    /// Clip files of this layout under `root`, sorted by path
+    fn clip_files(self, root: &Path) -> Vec<PathBuf> {
+        let mut files: Vec<PathBuf> = self
+            .folder_patterns()
+            .iter()
+            .flat_map(|pattern| find_folders(root, pattern))
+            .filter_map(|folder| std::fs::read_dir(folder).ok())
+            .flatten()
+            .filter_map(|entry| entry.ok().map(|entry| entry.path()))
+            .filter(|path| {
+                path.is_file()
+                    && path.extension().is_some_and(|ext| {
+                        self.extensions().contains(&ext.to_string_lossy().to_lowercase().as_str())
+                    })
+            })
+            .collect();
+        files.sort();
+        files.dedup();
+        files
+    }
+
+    /// Group the files of one recording together, by what the camera recorded
+    /// about them where it can: GoPro numbers its chapters and P2 clip XML names
+    /// the shot. The other layouts number spanned files one after another, so
+    /// neighbours are joined when the file size, streams and timecode line up.
+    fn group(self, files: Vec<CardFile>) -> Vec<Vec<CardFile>> {
+        let part = match self {
+            CardLayout::GoPro => |path: &Path| gopro_chapter(&file_stem(path)),
+            CardLayout::P2 => p2_shot,
+            _ => {
+                let mut groups: Vec<Vec<CardFile>> = Vec::new();
+                for file in files {
+                    match groups.last_mut() {
+                        Some(group) if continues(&group[group.len() - 1], &file) => {
+                            group.push(file)
+                        }
+                        _ => groups.push(vec![file]),
+                    }
+                }
+                return groups;
+            }
+        };
+
+        let mut recordings: BTreeMap<String, Vec<(u64, CardFile)>> = BTreeMap::new();
+        for file in files {
+            let (key, position) = part(&file.0).unwrap_or_else(|| (file_stem(&file.0), 0));
+            recordings.entry(key).or_default().push((position, file));
+        }
+        let mut groups: Vec<Vec<CardFile>> = recordings
+            .into_values()
+            .map(|mut parts| {
+                parts.sort_by_key(|(position, _)| *position);
+                parts.into_iter().map(|(_, file)| file).collect()
+            })
+            .collect();
+        // Recording order, which the file names follow
+        groups.sort_by(|a, b| a[0].0.cmp(&b[0].0));
+        groups
+    }
+}
+
+/// Per-channel audio files of a P2 picture file: "CONTENTS/VIDEO/0001AB.MXF"
+/// records its sound to "CONTENTS/AUDIO/0001AB00.MXF", "0001AB01.MXF"...
+/// Empty for any other file.
+pub fn p2_audio_files(video_path: &Path) -> Vec<PathBuf> {
+    let folder_name = |path: Option<&Path>| {
+        path.and_then(Path::file_name)
+            .map(|name| name.to_string_lossy().to_uppercase())
+            .unwrap_or_default()
+    };
+    let video_folder = video_path.parent();
+    let contents = video_folder.and_then(Path::parent);
+    if folder_name(video_folder) != "VIDEO" || folder_name(contents) != "CONTENTS" {
+        return Vec::new();
+    }
+    let Some(audio_folder) = contents.and_then(|contents| find_entry(contents, "AUDIO")) else {
+        return Vec::new();
+    };
+
+    let stem = file_stem(video_path).to_uppercase();
+    let mut files: Vec<PathBuf> = std::fs::read_dir(audio_folder)
+        .into_iter()
+        .flatten()
+        .filter_map(|entry| entry.ok().map(|entry| entry.path()))
+        .filter(|path| {
+            let audio_stem = file_stem(path).to_uppercase();
+            path.is_file()
+                && path.extension().is_some_and(|ext| ext.eq_ignore_ascii_case("mxf"))
+                && audio_stem.len() == stem.len() + 2
+                && audio_stem.starts_with(&stem)
+                && audio_stem[stem.len()..].chars().all(|c| c.is_ascii_digit())
+        })
+        .collect();
+    files.sort();
+    files
+}
+
+/// Shot and position in it of a P2 picture file, from its clip XML in
+/// "CONTENTS/CLIP". Clips spanned across files share the GlobalShotID, and
+/// OffsetInShot counts the frames recorded before each one.
+fn p2_shot(video_path: &Path) -> Option<(String, u64)> {
+    let contents = video_path.parent()?.parent()?;
+    let clip_folder = find_entry(contents, "CLIP")?;
+    let xml_file = find_entry(&clip_folder, &format!("{}.XML", file_stem(video_path)))?;
+    let xml = std::fs::read_to_string(xml_file).ok()?;
+    let shot = xml_text(&xml, "GlobalShotID")?;
+    let offset = xml_text(&xml, "OffsetInShot").and_then(|offset| offset.parse().ok());
+    Some((shot.to_string(), offset.unwrap_or(0)))
+}
+
+/// Text of the first `<tag>` element; P2 clip XML has no attributes on these
+fn xml_text<'a>(xml: &'a str, tag: &str) -> Option<&'a str> {
+    let start = xml.find(&format!("<{}>", tag))? + tag.len() + 2;
+    let end = start + xml[start..].find(&format!("</{}>", tag))?;
+    Some(xml[start..end].trim())
+}
+
+/// Entry of `folder` with this name, compared without regard to case
+fn find_entry(folder: &Path, name: &str) -> Option<PathBuf> {
+    std::fs::read_dir(folder)
+        .ok()?
+        .filter_map(|entry| entry.ok().map(|entry| entry.path()))
+        .find(|path| {
+            path.file_name().is_some_and(|entry| entry.to_string_lossy().eq_ignore_ascii_case(name))
+        })
+}
+
+/// Scan a card folder for the clips of every recognised camera layout
+pub async fn scan(folder_path: &str) -> Result<CardScan, TranscodeError> {
+    let root = Path::new(folder_path);
+    if !root.is_dir() {
+        return Err(TranscodeError::InvalidInput(format!("folder not found: {}", folder_path)));
+    }
+
+    let mut scan = CardScan { layouts: Vec::new(), clips: Vec::new(), skipped: Vec::new() };
+    for layout in CardLayout::ALL {
+        let paths = layout.clip_files(root);
+        if paths.is_empty() {
+            continue;
+        }
+        scan.layouts.push(layout);
+
+        let mut files = Vec::new();
+        for path in paths {
+            let path_text = path.to_string_lossy().to_string();
+            let bytes = std::fs::metadata(&path).map(|m| m.len()).unwrap_or(0);
+            match ffprobe::extract_metadata(&path_text).await {
+                Ok(metadata) => files.push((path, bytes, metadata)),
+                Err(e) => scan.skipped.push(format!("{}: {}", path_text, e)),
+            }
+        }
+
+        for group in layout.group(files) {
+            let inputs: Vec<MediaMetadata> =
+                group.iter().map(|(_, _, metadata)| metadata.clone()).collect();
+            scan.clips.push(CardClip {
+                name: file_stem(&group[0].0),
+                layout,
+                file_paths: inputs.iter().map(|input| input.file_path.clone()).collect(),
+                metadata: concat::joined_metadata(&inputs),
+            });
+        }
+    }
+
+    if scan.layouts.is_empty() {
+        return Err(TranscodeError::InvalidInput(format!(
+            "no camera card layout found in {}",
+            folder_path
+        )));
+    }
+    Ok(scan)
+}
+
+/// Whether `next` carries on the recording `previous` was split from: the
+/// previous file reached the split size, the streams match and, when both
+/// have timecode, `next` starts where `previous` ends
+fn continues(previous: &CardFile, next: &CardFile) -> bool {
+    let (_, previous_bytes, previous) = previous;
+    let (_, _, next) = next;
+    if *previous_bytes < SPAN_MIN_BYTES {
+        return false;
+    }
+    let inputs = [previous.clone(), next.clone()];
+    if !concat::differences(&inputs).is_ok_and(|differences| differences.is_empty()) {
+        return false;
+    }
+
+    let rate = previous.video.as_ref().and_then(|video| FrameRate::parse(&video.framerate));
+    let timecodes = (
+        previous.timecode.as_deref().and_then(Timecode::parse),
+        next.timecode.as_deref().and_then(Timecode::parse),
+    );
+    match (rate, timecodes) {
+        (Some(rate), (Some(start), Some(next_start))) => {
+            let expected = start.to_seconds(rate) + previous.duration_sec;
+            // Within a frame and a half, for containers that round the duration
+            (next_start.to_seconds(rate) - expected).abs() <= 1.5 / rate.fps()
+        }
+        _ => true,
+    }
+}
+
+/// Recording key and chapter of a GoPro file name: "GX020123" -> ("GX0123", 2).
+/// Older cameras name the first chapter GOPR0123 and the rest GP010123, GP020123...
+fn gopro_chapter(stem: &str) -> Option<(String, u64)> {
+    let stem = stem.to_uppercase();
+    if stem.len() != 8 || !stem.is_ascii() || !stem[4..].chars().all(|c| c.is_ascii_digit()) {
+        return None;
+    }
+    let number = &stem[4..];
+    if stem.starts_with("GOPR") {
+        return Some((format!("GP{}", number), 0));
+    }
+    let prefix = &stem[..2];
+    let chapter = stem[2..4].parse().ok()?;
+    matches!(prefix, "GP" | "GH" | "GX" | "GS").then(|| (format!("{}{}", prefix, number), chapter))
+}
+
+/// Folders under `root` matching a pattern such as "DCIM/*GOPRO"
+fn find_folders(root: &Path, pattern: &str) -> Vec<PathBuf> {
+    let mut folders = vec![root.to_path_buf()];
+    for component in pattern.split('/') {
+        let component = component.to_lowercase();
+        folders = folders
+            .iter()
+            .filter_map(|folder| std::fs::read_dir(folder).ok())
+            .flatten()
+            .filter_map(|entry| entry.ok().map(|entry| entry.path()))
+            .filter(|path| {
+                let name = path
+                    .file_name()
+                    .map(|name| name.to_string_lossy().to_lowercase())
+                    .unwrap_or_default();
+                path.is_dir()
+                    && match (component.strip_prefix('*'), component.strip_suffix('*')) {
+                        (Some(suffix), _) => name.ends_with(suffix),
+                        (_, Some(prefix)) => name.starts_with(prefix),
+                        _ => name == component,
+                    }
+            })
+            .collect();
+    }
+    folders
+}
+
+fn file_stem(path: &Path) -> String {
+    path.file_stem()
+        .map(|stem| stem.to_string_lossy().to_string())
+        .unwrap_or_default()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn clip_xml(shot: &str, offset: u64) -> String {
+        format!(
+            "<P2Main><ClipContent><ClipName>x</ClipName><Relation>\
+<OffsetInShot>{}</OffsetInShot><GlobalShotID>{}</GlobalShotID>\
+</Relation></ClipContent></P2Main>",
+            offset, shot
+        )
+    }
+
+    #[test]
+    fn p2_audio_and_shot_come_from_the_card() {
+        let root = std::env::temp_dir().join(format!("p2-{}", uuid::Uuid::new_v4()));
+        let contents = root.join("CONTENTS");
+        for folder in ["VIDEO", "AUDIO", "CLIP"] {
+            std::fs::create_dir_all(contents.join(folder)).unwrap();
+        }
+        let video = contents.join("VIDEO/0001AB.MXF");
+        let files = [
+            "VIDEO/0001AB.MXF",
+            "AUDIO/0001AB01.MXF",
+            "AUDIO/0001AB00.MXF",
+            "AUDIO/0002AB00.MXF",
+        ];
+        for file in files {
+            std::fs::write(contents.join(file), b"").unwrap();
+        }
+        std::fs::write(contents.join("CLIP/0001AB.XML"), clip_xml("060A2B34", 1500)).unwrap();
+
+        let audio = p2_audio_files(&video);
+        assert_eq!(
+            audio,
+            vec![contents.join("AUDIO/0001AB00.MXF"), contents.join("AUDIO/0001AB01.MXF")]
+        );
+        assert_eq!(p2_shot(&video), Some(("060A2B34".to_string(), 1500)));
+        assert!(p2_audio_files(&root.join("0001AB.MXF")).is_empty());
+
+        std::fs::remove_dir_all(root).unwrap();
+    }
+
+    #[test]
+    fn gopro_chapters_share_the_recording_key() {
+        assert_eq!(gopro_chapter("GX020123"), Some(("GX0123".to_string(), 2)));
+        assert_eq!(gopro_chapter("GOPR0123"), Some(("GP0123".to_string(), 0)));
+        assert_eq!(gopro_chapter("GP010123"), Some(("GP0123".to_string(), 1)));
+        assert_eq!(gopro_chapter("DJI_0001"), None);
+    }
+}
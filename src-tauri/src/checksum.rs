@@ -0,0 +1,246 @@
+use crate::error::TranscodeError;
+use crate::models::{ChecksumOptions, FileChecksum, HashAlgorithm};
+use md5::{Digest, Md5};
+use sha1::Sha1;
+use std::fs::{File, OpenOptions};
+use std::io::{Read, Write};
+use std::path::Path;
+use std::sync::Mutex;
+use xxhash_rust::xxh64::Xxh64;
+
+/// Batch jobs finish in parallel and append to the same manifest
+static MANIFEST_LOCK: Mutex<()> = Mutex::new(());
+
+/// Read size for hashing and copying; large enough to keep card readers busy
+pub const CHUNK_BYTES: usize = 8 * 1024 * 1024;
+
+/// Running checksum of a byte stream
+pub enum Hasher {
+    XxHash64(Box<Xxh64>),
+    Md5(Md5),
+    Sha1(Sha1),
+}
+
+impl Hasher {
+    pub fn new(algorithm: HashAlgorithm) -> Self {
+        match algorithm {
+            HashAlgorithm::XxHash64 => Hasher::XxHash64(Box::new(Xxh64::new(0))),
+            HashAlgorithm::Md5 => Hasher::Md5(Md5::new()),
+            HashAlgorithm::Sha1 => Hasher::Sha1(Sha1::new()),
+        }
+    }
+
+    pub fn update(&mut self, bytes: &[u8]) {
+        match self {
+            Hasher::XxHash64(hasher) => hasher.update(bytes),
+            Hasher::Md5(hasher) => hasher.update(bytes),
+            Hasher::Sha1(hasher) => hasher.update(bytes),
+        }
+    }
+
+    /// Lower-case hex digest; xxHash64 is written big-endian, as MHL expects
+    pub fn finish(self) -> String {
+        match self {
+            Hasher::XxHash64(hasher) => format!("{:016x}", hasher.digest()),
+            Hasher::Md5(hasher) => hex(&hasher.finalize()),
+            Hasher::Sha1(hasher) => hex(&hasher.finalize()),
+        }
+    }
+}
+
+impl HashAlgorithm {
+    /// Element name of this hash in an MHL manifest
+    pub fn mhl_element(self) -> &'static str {
+        match self {
+            HashAlgorithm::XxHash64 => "xxhash64be",
+            HashAlgorithm::Md5 => "md5",
+            HashAlgorithm::Sha1 => "sha1",
+        }
+    }
+
+    /// Sidecar file extension
+    fn sidecar_extension(self) -> &'static str {
+        match self {
+            HashAlgorithm::XxHash64 => "xxhash",
+            HashAlgorithm::Md5 => "md5",
+            HashAlgorithm::Sha1 => "sha1",
+        }
+    }
+}
+
+impl ChecksumOptions {
+    /// Check the manifest can be written, called when the job is submitted
+    pub fn validate(&self) -> Result<(), TranscodeError> {
+        if let Some(manifest) = &self.manifest_path {
+            let folder = Path::new(manifest).parent().filter(|p| !p.as_os_str().is_empty());
+            if folder.is_some_and(|folder| !folder.is_dir()) {
+                return Err(TranscodeError::InvalidOption(format!(
+                    "checksum manifest folder not found: {}",
+                    manifest
+                )));
+            }
+        }
+        Ok(())
+    }
+
+    /// Write the hashes as md5sum-style lines: a sidecar per file in
+    /// `output_folder`, or appended to the manifest with full paths. Source
+    /// sidecars are named apart from the outputs, e.g. "A001.mov.source.md5"
+    /// next to "A001.mov.md5", numbered when several sources are joined, and
+    /// hold the source's full path, as the output folder may hold a file of
+    /// the same name. Each sidecar is replaced through a temporary file, so one
+    /// left by an earlier encode never outlives the output it described.
+    pub fn write(
+        &self,
+        sources: &[FileChecksum],
+        outputs: &[FileChecksum],
+        output_folder: &Path,
+    ) -> Result<(), TranscodeError> {
+        if let Some(manifest) = &self.manifest_path {
+            let lines: String = sources
+                .iter()
+                .chain(outputs)
+                .map(|checksum| format!("{}  {}\n", checksum.hash, checksum.path))
+                .collect();
+            let _guard = MANIFEST_LOCK.lock().unwrap_or_else(|e| e.into_inner());
+            OpenOptions::new()
+                .create(true)
+                .append(true)
+                .open(manifest)?
+                .write_all(lines.as_bytes())?;
+            return Ok(());
+        }
+
+        let extension = self.algorithm.sidecar_extension();
+        let source_sidecars = sources.iter().enumerate().map(|(index, checksum)| {
+            let role = match sources.len() {
+                1 => "source".to_string(),
+                _ => format!("source{}", index + 1),
+            };
+            (checksum, format!("{}.{}", role, extension), checksum.path.clone())
+        });
+        let output_sidecars = outputs.iter().map(|checksum| {
+            (checksum, extension.to_string(), file_name(&checksum.path))
+        });
+
+        for (checksum, suffix, listed_path) in source_sidecars.chain(output_sidecars) {
+            let name = file_name(&checksum.path);
+            let sidecar = output_folder.join(format!("{}.{}", name, suffix));
+            let partial = output_folder.join(format!("{}.{}.partial", name, suffix));
+            std::fs::write(&partial, format!("{}  {}\n", checksum.hash, listed_path))?;
+            std::fs::rename(&partial, &sidecar)?;
+        }
+        Ok(())
+    }
+}
+
+/// Hash every file in order
+pub fn hash_files(
+    paths: &[String],
+    algorithm: HashAlgorithm,
+) -> Result<Vec<FileChecksum>, TranscodeError> {
+    paths
+        .iter()
+        .map(|path| {
+            Ok(FileChecksum {
+                path: path.clone(),
+                algorithm,
+                hash: hash_file(Path::new(path), algorithm)?,
+            })
+        })
+        .collect()
+}
+
+/// Hash a whole file
+fn hash_file(path: &Path, algorithm: HashAlgorithm) -> std::io::Result<String> {
+    let mut file = File::open(path)?;
+    let mut hasher = Hasher::new(algorithm);
+    let mut buffer = vec![0u8; CHUNK_BYTES];
+    loop {
+        let read = file.read(&mut buffer)?;
+        if read == 0 {
+            break;
+        }
+        hasher.update(&buffer[..read]);
+    }
+    Ok(hasher.finish())
+}
+
+/// Last component of a path, empty when it has none
+fn file_name(path: &str) -> String {
+    Path::new(path)
+        .file_name()
+        .map(|name| name.to_string_lossy().to_string())
+        .unwrap_or_default()
+}
+
+fn hex(bytes: &[u8]) -> String {
+    bytes.iter().map(|byte| format!("{:02x}", byte)).collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn digest(algorithm: HashAlgorithm, bytes: &[u8]) -> String {
+        let mut hasher = Hasher::new(algorithm);
+        hasher.update(bytes);
+        hasher.finish()
+    }
+
+    #[test]
+    fn xxhash64_is_written_big_endian_for_mhl() {
+        // Reference values from the xxHash specification, most significant byte first
+        assert_eq!(digest(HashAlgorithm::XxHash64, b""), "ef46db3751d8e999");
+        assert_eq!(digest(HashAlgorithm::XxHash64, b"abc"), "44bc2cf5ad770999");
+        assert_eq!(HashAlgorithm::XxHash64.mhl_element(), "xxhash64be");
+    }
+
+    #[test]
+    fn chunked_updates_match_one_update() {
+        let mut hasher = Hasher::new(HashAlgorithm::XxHash64);
+        hasher.update(b"a");
+        hasher.update(b"bc");
+        assert_eq!(hasher.finish(), digest(HashAlgorithm::XxHash64, b"abc"));
+    }
+
+    #[test]
+    fn md5_and_sha1_are_lower_case_hex() {
+        assert_eq!(digest(HashAlgorithm::Md5, b"abc"), "900150983cd24fb0d6963f7d28e17f72");
+        assert_eq!(
+            digest(HashAlgorithm::Sha1, b"abc"),
+            "a9993e364706816aba3e25717850c26c9cd0d89d"
+        );
+    }
+
+    #[test]
+    fn sidecars_name_sources_apart_and_replace_stale_ones() {
+        let folder = std::env::temp_dir().join(format!("sidecars-{}", uuid::Uuid::new_v4()));
+        std::fs::create_dir_all(&folder).unwrap();
+        let checksum = |path: &str, hash: &str| FileChecksum {
+            path: path.to_string(),
+            algorithm: HashAlgorithm::Md5,
+            hash: hash.to_string(),
+        };
+        let options = ChecksumOptions { algorithm: HashAlgorithm::Md5, manifest_path: None };
+
+        let sources = [checksum("/card/A001.mov", "aa")];
+        let outputs = [checksum("/out/A001.mov", "bb")];
+        options.write(&sources, &outputs, &folder).unwrap();
+        let read = |name: &str| std::fs::read_to_string(folder.join(name)).unwrap();
+        assert_eq!(read("A001.mov.source.md5"), "aa  /card/A001.mov\n");
+        assert_eq!(read("A001.mov.md5"), "bb  A001.mov\n");
+
+        // Encoding into the same folder again replaces the sidecars with the new hashes
+        options.write(&sources, &[checksum("/out/A001.mov", "ff")], &folder).unwrap();
+        assert_eq!(read("A001.mov.md5"), "ff  A001.mov\n");
+        assert!(!folder.join("A001.mov.md5.partial").exists());
+
+        let joined = [checksum("/card1/C0001.MP4", "cc"), checksum("/card2/C0001.MP4", "dd")];
+        options.write(&joined, &[checksum("/out/joined.mov", "ee")], &folder).unwrap();
+        assert_eq!(read("C0001.MP4.source1.md5"), "cc  /card1/C0001.MP4\n");
+        assert_eq!(read("C0001.MP4.source2.md5"), "dd  /card2/C0001.MP4\n");
+
+        std::fs::remove_dir_all(folder).unwrap();
+    }
+}
@@ -0,0 +1,263 @@
+use crate::models::{ColorConversion, ColorRange, ColorStandard, ToneMapping, VideoStream};
+
+/// Colour tags written to the output (ffmpeg option names)
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct ColorTags {
+    pub primaries: Option<String>,
+    pub transfer: Option<String>,
+    pub space: Option<String>,
+    pub range: Option<String>,
+}
+
+impl ColorTags {
+    /// Tags for the output: the source tags, overridden by any explicit conversion
+    pub fn for_output(video: &VideoStream, conversion: Option<&ColorConversion>) -> Self {
+        let mut tags = ColorTags {
+            primaries: video.color_primaries.clone(),
+            transfer: video.color_transfer.clone(),
+            space: video.color_space.clone(),
+            range: video.color_range.clone(),
+        };
+
+        if let Some(conversion) = conversion {
+            if let Some(standard) = conversion.standard {
+                tags.primaries = Some(standard.primaries_tag().to_string());
+                tags.transfer = Some(standard.transfer_tag().to_string());
+                tags.space = Some(standard.matrix_tag().to_string());
+            }
+            if let Some(range) = conversion.range {
+                tags.range = Some(range.tag().to_string());
+            }
+        }
+
+        tags
+    }
+
+    /// Tags for tone-mapped output: Rec.709 SDR, limited range
+    pub fn rec709() -> Self {
+        ColorTags {
+            primaries: Some(ColorStandard::Rec709.primaries_tag().to_string()),
+            transfer: Some(ColorStandard::Rec709.transfer_tag().to_string()),
+            space: Some(ColorStandard::Rec709.matrix_tag().to_string()),
+            range: Some(ColorRange::Limited.tag().to_string()),
+        }
+    }
+
+    /// ffmpeg output options that tag the encoded stream
+    pub fn to_ffmpeg_args(&self) -> Vec<String> {
+        let mut args = Vec::new();
+        let pairs = [
+            ("-color_primaries", &self.primaries),
+            ("-color_trc", &self.transfer),
+            ("-colorspace", &self.space),
+            ("-color_range", &self.range),
+        ];
+        for (flag, value) in pairs {
+            if let Some(value) = value {
+                args.push(flag.to_string());
+                args.push(value.clone());
+            }
+        }
+        args
+    }
+}
+
+impl ColorConversion {
+    /// Build the zscale filter for this conversion, None when nothing changes
+    pub fn zscale_filter(&self, video: &VideoStream) -> Option<String> {
+        if self.standard.is_none() && self.range.is_none() {
+            return None;
+        }
+
+        // zscale needs to know the input; untagged footage is assumed to be
+        // Rec.601 at SD sizes and Rec.709 otherwise, as NLEs do
+        let assumed = assumed_standard(video);
+        let primaries_in = video.color_primaries.as_deref().and_then(zscale_primaries);
+        let transfer_in = video.color_transfer.as_deref().and_then(zscale_transfer);
+        let matrix_in = video.color_space.as_deref().and_then(zscale_matrix);
+        let range_in = video.color_range.as_deref().and_then(zscale_range);
+
+        let mut params = vec![
+            format!("primariesin={}", primaries_in.unwrap_or(assumed.zscale_primaries())),
+            format!("transferin={}", transfer_in.unwrap_or(assumed.zscale_transfer())),
+            format!("matrixin={}", matrix_in.unwrap_or(assumed.zscale_matrix())),
+            format!("rangein={}", range_in.unwrap_or("limited")),
+        ];
+
+        if let Some(standard) = self.standard {
+            params.push(format!("primaries={}", standard.zscale_primaries()));
+            params.push(format!("transfer={}", standard.zscale_transfer()));
+            params.push(format!("matrix={}", standard.zscale_matrix()));
+        }
+        if let Some(range) = self.range {
+            params.push(format!("range={}", range.zscale_name()));
+        }
+
+        Some(format!("zscale={}", params.join(":")))
+    }
+}
+
+impl ToneMapping {
+    /// Build the HDR to Rec.709 SDR chain, None when off or the source is SDR.
+    /// The chain linearises, tone-maps in float RGB, then converts to `pix_fmt`
+    /// inside zscale so the Rec.709 matrix is used rather than swscale defaults.
+    pub fn filter_chain(&self, video: &VideoStream, pix_fmt: &str) -> Option<String> {
+        video.hdr_format?;
+
+        let operator = match self {
+            ToneMapping::Off => return None,
+            ToneMapping::Hable => "hable",
+            ToneMapping::Mobius => "mobius",
+            ToneMapping::Reinhard => "reinhard",
+        };
+
+        let transfer_in = video.color_transfer.as_deref().and_then(zscale_transfer);
+        let primaries_in = video.color_primaries.as_deref().and_then(zscale_primaries);
+        let matrix_in = video.color_space.as_deref().and_then(zscale_matrix);
+        let range_in = video.color_range.as_deref().and_then(zscale_range);
+
+        Some(format!(
+            "zscale=transferin={}:primariesin={}:matrixin={}:rangein={}:transfer=linear:npl=100,\
+format=gbrpf32le,\
+zscale=primaries=709,\
+tonemap=tonemap={}:desat=0,\
+zscale=transfer=709:matrix=709:range=limited,\
+format={}",
+            transfer_in.unwrap_or("smpte2084"),
+            primaries_in.unwrap_or("2020"),
+            matrix_in.unwrap_or("2020_ncl"),
+            range_in.unwrap_or("limited"),
+            operator,
+            pix_fmt,
+        ))
+    }
+}
+
+impl ColorStandard {
+    /// ffmpeg `-color_primaries` value
+    pub fn primaries_tag(&self) -> &'static str {
+        match self {
+            ColorStandard::Rec601 => "smpte170m",
+            ColorStandard::Rec709 => "bt709",
+            ColorStandard::Rec2020 => "bt2020",
+        }
+    }
+
+    /// ffmpeg `-color_trc` value
+    pub fn transfer_tag(&self) -> &'static str {
+        match self {
+            ColorStandard::Rec601 => "smpte170m",
+            ColorStandard::Rec709 => "bt709",
+            ColorStandard::Rec2020 => "bt2020-10",
+        }
+    }
+
+    /// ffmpeg `-colorspace` value
+    pub fn matrix_tag(&self) -> &'static str {
+        match self {
+            ColorStandard::Rec601 => "smpte170m",
+            ColorStandard::Rec709 => "bt709",
+            ColorStandard::Rec2020 => "bt2020nc",
+        }
+    }
+
+    fn zscale_primaries(&self) -> &'static str {
+        match self {
+            ColorStandard::Rec601 => "170m",
+            ColorStandard::Rec709 => "709",
+            ColorStandard::Rec2020 => "2020",
+        }
+    }
+
+    fn zscale_transfer(&self) -> &'static str {
+        match self {
+            ColorStandard::Rec601 => "601",
+            ColorStandard::Rec709 => "709",
+            ColorStandard::Rec2020 => "2020_10",
+        }
+    }
+
+    fn zscale_matrix(&self) -> &'static str {
+        match self {
+            ColorStandard::Rec601 => "170m",
+            ColorStandard::Rec709 => "709",
+            ColorStandard::Rec2020 => "2020_ncl",
+        }
+    }
+}
+
+impl ColorRange {
+    /// ffmpeg `-color_range` value
+    pub fn tag(&self) -> &'static str {
+        match self {
+            ColorRange::Limited => "tv",
+            ColorRange::Full => "pc",
+        }
+    }
+
+    fn zscale_name(&self) -> &'static str {
+        match self {
+            ColorRange::Limited => "limited",
+            ColorRange::Full => "full",
+        }
+    }
+}
+
+/// Standard to assume for untagged footage
+fn assumed_standard(video: &VideoStream) -> ColorStandard {
+    if video.height <= 576 {
+        ColorStandard::Rec601
+    } else {
+        ColorStandard::Rec709
+    }
+}
+
+/// Map ffprobe primaries names to zscale names
+fn zscale_primaries(tag: &str) -> Option<&'static str> {
+    match tag {
+        "bt709" => Some("709"),
+        "bt2020" => Some("2020"),
+        "smpte170m" => Some("170m"),
+        "smpte240m" => Some("240m"),
+        "bt470bg" => Some("bt470bg"),
+        "smpte431" => Some("smpte431"),
+        "smpte432" => Some("smpte432"),
+        _ => None,
+    }
+}
+
+/// Map ffprobe transfer names to zscale names
+fn zscale_transfer(tag: &str) -> Option<&'static str> {
+    match tag {
+        "bt709" => Some("709"),
+        "smpte170m" | "bt470bg" | "bt470m" => Some("601"),
+        "bt2020-10" => Some("2020_10"),
+        "bt2020-12" => Some("2020_12"),
+        "smpte2084" => Some("smpte2084"),
+        "arib-std-b67" => Some("arib-std-b67"),
+        "iec61966-2-1" => Some("iec61966-2-1"),
+        "linear" => Some("linear"),
+        _ => None,
+    }
+}
+
+/// Map ffprobe matrix names to zscale names
+fn zscale_matrix(tag: &str) -> Option<&'static str> {
+    match tag {
+        "bt709" => Some("709"),
+        "bt470bg" => Some("470bg"),
+        "smpte170m" => Some("170m"),
+        "bt2020nc" => Some("2020_ncl"),
+        "bt2020c" => Some("2020_cl"),
+        _ => None,
+    }
+}
+
+/// Map ffprobe range names to zscale names
+fn zscale_range(tag: &str) -> Option<&'static str> {
+    match tag {
+        "tv" => Some("limited"),
+        "pc" => Some("full"),
+        _ => None,
+    }
+}
@@ -1,6 +1,15 @@
+use crate::card;
 use crate::error::{CmdError, TranscodeError};
 use crate::ffmpeg::{self, transcode, validator, FfmpegSource};
-use crate::models::{BatchTranscodeRequest, FfmpegAvailability, MediaMetadata, TranscodeRequest};
+use crate::models::{
+    BatchTranscodeRequest, CardScan, ConcatTranscodeRequest, CropMode, CropRect, FfmpegAvailability,
+    MediaMetadata, OffloadRequest, OutputPreset, SegmentCheck, TimeSegment, TranscodeOptions,
+    TranscodeRequest,
+};
+use crate::offload;
+use crate::remux;
+use crate::segment;
+use crate::timecode::parse_framerate;
 use tauri::Window;
 
 /// Check if ffmpeg and ffprobe are available on the system
@@ -33,11 +42,80 @@ fn source_to_string(source: FfmpegSource) -> String {
     }
 }
 
-/// Extract metadata from a video file using ffprobe
+/// Extract metadata from a video or audio file using ffprobe.
+/// `scan_timestamps` additionally scans packet timestamps to detect VFR.
 #[tauri::command]
-pub async fn get_media_info(file_path: String) -> Result<MediaMetadata, CmdError> {
+pub async fn get_media_info(
+    file_path: String,
+    scan_timestamps: Option<bool>,
+) -> Result<MediaMetadata, CmdError> {
     validator::ensure_ffprobe()?;
-    Ok(ffmpeg::ffprobe::extract_metadata(&file_path).await?)
+    let mut metadata = ffmpeg::ffprobe::extract_metadata(&file_path).await?;
+    if let Some(video) = metadata.video.as_mut() {
+        if scan_timestamps.unwrap_or(false) && !video.is_vfr {
+            video.is_vfr = ffmpeg::ffprobe::scan_vfr(&file_path).await?;
+        }
+    }
+    Ok(metadata)
+}
+
+/// Detect black bars with cropdetect sampled across the file
+#[tauri::command]
+pub async fn detect_crop(file_path: String) -> Result<CropRect, CmdError> {
+    validator::ensure_ffmpeg()?;
+    validator::ensure_ffprobe()?;
+    Ok(ffmpeg::cropdetect::detect_crop(&file_path).await?)
+}
+
+/// Scan a mounted camera card and list its recordings, with files split by
+/// the camera grouped into one clip
+#[tauri::command]
+pub async fn scan_card(folder_path: String) -> Result<CardScan, CmdError> {
+    validator::ensure_ffprobe()?;
+    Ok(card::scan(&folder_path).await?)
+}
+
+/// Check each file's segment against the file before submitting, reporting
+/// what would be clamped and which segments would be rejected.
+/// `segments` lines up with `input_paths` like a batch request's.
+#[tauri::command]
+pub async fn validate_segments(
+    input_paths: Vec<String>,
+    segments: Vec<Option<TimeSegment>>,
+) -> Result<Vec<SegmentCheck>, CmdError> {
+    validator::ensure_ffprobe()?;
+
+    let mut checks = Vec::new();
+    for (index, input_path) in input_paths.into_iter().enumerate() {
+        let Some(segment) = segments.get(index).cloned().flatten() else {
+            checks.push(SegmentCheck {
+                input_path,
+                segment: None,
+                corrections: Vec::new(),
+                error: None,
+            });
+            continue;
+        };
+
+        let checked = ffmpeg::ffprobe::extract_metadata(&input_path)
+            .await
+            .and_then(|metadata| segment.effective(&metadata));
+        checks.push(match checked {
+            Ok((segment, corrections)) => SegmentCheck {
+                input_path,
+                segment: Some(segment),
+                corrections,
+                error: None,
+            },
+            Err(e) => SegmentCheck {
+                input_path,
+                segment: None,
+                corrections: Vec::new(),
+                error: Some(e.to_string()),
+            },
+        });
+    }
+    Ok(checks)
 }
 
 /// Start transcoding - returns immediately, progress sent via events
@@ -48,6 +126,20 @@ pub async fn start_transcode(
 ) -> Result<String, CmdError> {
     validator::ensure_ffmpeg()?;
 
+    validate_options(&request.options, request.preset).await?;
+    if let Some(segment) = &request.segment {
+        segment.validate()?;
+    }
+    if let Some(list) = &request.segment_list {
+        if request.segment.is_some() {
+            return Err(TranscodeError::InvalidSegment(
+                "give either a segment or a segment list, not both".to_string(),
+            )
+            .into());
+        }
+        segment::validate_list(list)?;
+    }
+
     // Generate a job ID
     let job_id = uuid::Uuid::new_v4().to_string();
 
@@ -64,50 +156,140 @@ pub async fn start_batch_transcode(
     window: Window,
 ) -> Result<String, CmdError> {
     validator::ensure_ffmpeg()?;
+    validate_batch(&request).await?;
+    Ok(transcode::spawn_batch(request, window)?)
+}
 
+/// Check a batch request before any of its jobs start
+async fn validate_batch(request: &BatchTranscodeRequest) -> Result<(), TranscodeError> {
     if request.input_paths.is_empty() {
-        return Err(TranscodeError::InvalidInput("No files provided".to_string()).into());
+        return Err(TranscodeError::InvalidInput("No files provided".to_string()));
     }
 
     if request.input_paths.len() != request.output_paths.len() {
         return Err(TranscodeError::InvalidInput(
             "input_paths and output_paths must have the same length".to_string()
-        ).into());
-    }
-
-    // Generate a batch ID
-    let batch_id = uuid::Uuid::new_v4().to_string();
-    let total_files = request.input_paths.len();
-
-    // Start transcode job for each file in parallel
-    for (index, (input_path, output_path)) in request.input_paths.iter().zip(request.output_paths.iter()).enumerate() {
-        let file_index = index;
-        let total = total_files;
-        let batch_id_clone = batch_id.clone();
-        // Window is internally reference-counted; cloning is cheap here.
-        let window_clone = window.clone();
-
-        // Get the segment for this file (if any)
-        let segment = request.segments.as_ref()
-            .and_then(|segments| segments.get(index)
-            .and_then(|s| s.as_ref().cloned()));
-
-        let transcode_request = TranscodeRequest {
-            input_path: input_path.clone(),
-            output_path: output_path.clone(),
-            preset: request.preset,
-            segment,
-        };
+        ));
+    }
+
+    validate_options(&request.options, request.preset).await?;
+    for segment in request.segments.iter().flatten().flatten() {
+        segment.validate()?;
+    }
+    for list in request.segment_lists.iter().flatten().flatten() {
+        segment::validate_list(list)?;
+    }
+    Ok(())
+}
+
+/// Join several files, in order, into one output
+#[tauri::command]
+pub async fn start_concat_transcode(
+    request: ConcatTranscodeRequest,
+    window: Window,
+) -> Result<String, CmdError> {
+    validator::ensure_ffmpeg()?;
+
+    if request.input_paths.len() < 2 {
+        return Err(TranscodeError::InvalidInput(
+            "concat needs at least two files".to_string(),
+        )
+        .into());
+    }
 
-        // Spawn each file in its own thread
-        transcode::spawn_batch_transcode_job(
-            batch_id_clone,
-            file_index,
-            total,
-            transcode_request,
-            window_clone,
-        )?;
+    validate_options(&request.options, request.preset).await?;
+    // The shifted audio would be read from the first file only
+    if request.options.audio_offset_ms.is_some_and(|ms| ms != 0) {
+        return Err(TranscodeError::InvalidOption(
+            "audio offset is not supported when joining files".to_string(),
+        )
+        .into());
     }
 
-    Ok(batch_id)
+    let job_id = uuid::Uuid::new_v4().to_string();
+    transcode::spawn_concat_job(request, window)?;
+
+    Ok(job_id)
+}
+
+/// Copy folders to every destination and verify the copies. A chained batch
+/// transcode starts from the verified copies once the offload completes.
+#[tauri::command]
+pub async fn start_offload(request: OffloadRequest, window: Window) -> Result<String, CmdError> {
+    offload::validate(&request)?;
+    if let Some(batch) = &request.then_transcode {
+        validator::ensure_ffmpeg()?;
+        validate_batch(batch).await?;
+    }
+
+    let offload_id = uuid::Uuid::new_v4().to_string();
+    offload::spawn_offload_job(offload_id.clone(), request, window);
+
+    Ok(offload_id)
+}
+
+/// Validate processing options up front so bad files fail at submission, not mid-batch
+async fn validate_options(
+    options: &TranscodeOptions,
+    preset: OutputPreset,
+) -> Result<(), TranscodeError> {
+    if let Some(rate) = &options.frame_rate {
+        if parse_framerate(rate).is_none() {
+            return Err(TranscodeError::InvalidOption(format!("invalid frame rate: {}", rate)));
+        }
+    }
+    if let Some(CropMode::Manual(rect)) = options.crop {
+        if rect.width == 0 || rect.height == 0 {
+            return Err(TranscodeError::InvalidOption("crop rectangle is empty".to_string()));
+        }
+        // Every output is chroma subsampled, like cropdetect's rectangles (round=2)
+        if [rect.x, rect.y, rect.width, rect.height].iter().any(|value| value % 2 != 0) {
+            return Err(TranscodeError::InvalidOption(format!(
+                "crop {}x{}+{}+{} must use even sizes and offsets for 4:2:0 and 4:2:2 outputs",
+                rect.width, rect.height, rect.x, rect.y
+            )));
+        }
+    }
+    if let Some(lut) = &options.lut {
+        lut.validate()?;
+    }
+    if let Some(burn_in) = &options.burn_in {
+        burn_in.validate()?;
+    }
+    if let Some(watermark) = &options.watermark {
+        watermark.validate().await?;
+    }
+    if let Some(loudness) = &options.loudness {
+        loudness.validate()?;
+    }
+    if let Some(layout) = &options.audio_layout {
+        layout.validate()?;
+    }
+    if let Some(external) = &options.external_audio {
+        external.validate()?;
+    }
+    if let Some(format) = &options.audio_format {
+        format.validate(preset)?;
+    }
+    if let Some(output) = &options.segment_output {
+        output.validate()?;
+    }
+    if let Some(checksum) = &options.checksum {
+        checksum.validate()?;
+    }
+    if preset.is_remux() {
+        remux::validate_options(options)?;
+    }
+    if let Some(tracks) = &options.audio_tracks {
+        // Track indices are checked against each file when its job starts
+        if let Some(index) = tracks.iter().enumerate().find_map(|(i, track)| {
+            tracks[..i].contains(track).then_some(track)
+        }) {
+            return Err(TranscodeError::InvalidOption(format!(
+                "audio track {} is selected twice",
+                index
+            )));
+        }
+    }
+    Ok(())
 }
@@ -18,6 +18,15 @@ pub enum TranscodeError {
     #[error("Invalid input file: {0}")]
     InvalidInput(String),
 
+    #[error("Invalid LUT: {0}")]
+    InvalidLut(String),
+
+    #[error("Invalid segment: {0}")]
+    InvalidSegment(String),
+
+    #[error("Invalid option: {0}")]
+    InvalidOption(String),
+
     #[error("IO error: {0}")]
     IoError(#[from] std::io::Error),
 
@@ -0,0 +1,315 @@
+use crate::error::TranscodeError;
+use crate::ffmpeg::ffprobe;
+use crate::models::{ConcatSources, ConcatTranscodeRequest, MediaMetadata, TranscodeRequest};
+use crate::preset::{rotation_filter, upright_input_args};
+use crate::timecode::FrameRate;
+use std::path::Path;
+
+impl ConcatSources {
+    /// Input arguments: the lists for the concat demuxer, the picture's then one
+    /// per audio file, or every file for the filter graph. `clear_matrix` resets
+    /// the display matrix of rotated pictures, see `upright_input_args`.
+    pub fn input_args(&self, clear_matrix: bool) -> Vec<String> {
+        match &self.list_path {
+            Some(list_path) => {
+                // Rotation is applied by the filters from the first input's display matrix
+                let rotation = self.inputs.first().map_or(0, input_rotation);
+                let mut args = upright_input_args(rotation, clear_matrix);
+                for list_path in std::iter::once(list_path).chain(&self.audio_list_paths) {
+                    args.extend([
+                        "-f".to_string(),
+                        "concat".to_string(),
+                        // The list holds absolute paths, which the demuxer only reads when unsafe
+                        "-safe".to_string(),
+                        "0".to_string(),
+                        "-i".to_string(),
+                        list_path.clone(),
+                    ]);
+                }
+                args
+            }
+            None => self
+                .inputs
+                .iter()
+                .flat_map(|input| {
+                    // Each input is turned upright by its own chain in `video_graph`
+                    let mut args = upright_input_args(input_rotation(input), clear_matrix);
+                    args.extend(["-i".to_string(), input.file_path.clone()]);
+                    args
+                })
+                .collect(),
+        }
+    }
+
+    /// Number of inputs added by `input_args`
+    pub fn input_count(&self) -> usize {
+        match self.list_path {
+            Some(_) => 1 + self.audio_list_paths.len(),
+            None => self.inputs.len(),
+        }
+    }
+
+    /// Filter graph scaling, padding and retiming every picture to the first
+    /// input's, then joining them on `[vjoin]`. None when the demuxer joins them.
+    pub fn video_graph(&self) -> Option<Vec<String>> {
+        if self.list_path.is_some() {
+            return None;
+        }
+        let target = self.inputs.first()?.video.as_ref()?;
+        let (width, height) = (target.display_width, target.display_height);
+
+        let mut graph = Vec::new();
+        let mut parts = String::new();
+        for (index, input) in self.inputs.iter().enumerate() {
+            let rotate = input
+                .video
+                .as_ref()
+                .and_then(|video| rotation_filter(video.rotation))
+                .map(|filter| format!("{},", filter))
+                .unwrap_or_default();
+            graph.push(format!(
+                "[{}:v:0]{}scale={w}:{h}:force_original_aspect_ratio=decrease,\
+pad={w}:{h}:(ow-iw)/2:(oh-ih)/2,setsar=1,fps={}[vcat{}]",
+                index,
+                rotate,
+                target.framerate,
+                index,
+                w = width,
+                h = height
+            ));
+            parts.push_str(&format!("[vcat{}]", index));
+        }
+        graph.push(format!("{}concat=n={}:v=1:a=0[vjoin]", parts, self.inputs.len()));
+        Some(graph)
+    }
+
+    /// Filter graph joining audio stream `track` of every input on `[acat{track}]`.
+    /// Each part is padded or cut to its input's length so the sound stays with
+    /// the picture across the joins. None when the demuxer joins them.
+    pub fn audio_graph(&self, track: u32) -> Option<Vec<String>> {
+        if self.list_path.is_some() {
+            return None;
+        }
+        let mut graph = Vec::new();
+        let mut parts = String::new();
+        for (index, input) in self.inputs.iter().enumerate() {
+            graph.push(format!(
+                "[{}:a:{}]apad,atrim=end={:.6}[acat{}_{}]",
+                index, track, input.duration_sec, track, index
+            ));
+            parts.push_str(&format!("[acat{}_{}]", track, index));
+        }
+        graph.push(format!(
+            "{}concat=n={}:v=0:a=1[acat{}]",
+            parts,
+            self.inputs.len(),
+            track
+        ));
+        Some(graph)
+    }
+
+    /// Stream or filter graph label carrying audio stream `track` of the joined inputs
+    pub fn audio_label(&self, track: u32) -> String {
+        match self.list_path {
+            Some(_) if !self.audio_list_paths.is_empty() => format!("{}:a:0", track + 1),
+            Some(_) => format!("0:a:{}", track),
+            None => format!("acat{}", track),
+        }
+    }
+
+    /// Every demuxer list written for the job
+    pub fn list_paths(&self) -> impl Iterator<Item = &String> {
+        self.list_path.iter().chain(&self.audio_list_paths)
+    }
+}
+
+/// Probe every input of a concat job and work out how to join them. Returns the
+/// request the transcode runs, whose `concat` holds the inputs, and metadata
+/// describing the joined source. Writes the demuxer list when the inputs match.
+pub async fn prepare(
+    request: &ConcatTranscodeRequest,
+) -> Result<(TranscodeRequest, MediaMetadata), TranscodeError> {
+    let mut inputs = Vec::new();
+    for input_path in &request.input_paths {
+        inputs.push(
+            ffprobe::extract_metadata(input_path)
+                .await
+                .map_err(|e| TranscodeError::MediaInfoFailed(e.to_string()))?,
+        );
+    }
+
+    let differences = differences(&inputs)?;
+    if request.preset.is_remux() && !differences.is_empty() {
+        return Err(TranscodeError::InvalidInput(format!(
+            "the inputs differ ({}), so they cannot be joined by stream copy; \
+choose an encoding preset",
+            differences.join(", ")
+        )));
+    }
+    let separate_audio = inputs.iter().any(|input| !input.audio_files.is_empty());
+    if let Some(input) = inputs
+        .iter()
+        .find(|input| input.audio_files.len() != inputs[0].audio_files.len())
+        .filter(|_| separate_audio)
+    {
+        return Err(TranscodeError::InvalidInput(format!(
+            "{} and {} cannot be joined, only one of them has its audio in separate files",
+            inputs[0].file_path, input.file_path
+        )));
+    }
+    if separate_audio && !differences.is_empty() {
+        return Err(TranscodeError::InvalidInput(format!(
+            "the inputs differ ({}), and their audio is recorded to separate files, \
+so they can only be joined as they are",
+            differences.join(", ")
+        )));
+    }
+    let (list_path, audio_list_paths) = if differences.is_empty() {
+        let list_path = write_list(inputs.iter().map(|input| input.file_path.as_str()))?;
+        let mut audio_list_paths = Vec::new();
+        if separate_audio {
+            for track in 0..inputs[0].audio_files.len() {
+                let paths = inputs.iter().map(|input| input.audio_files[track].as_str());
+                audio_list_paths.push(write_list(paths)?);
+            }
+        }
+        (Some(list_path), audio_list_paths)
+    } else {
+        (None, Vec::new())
+    };
+
+    let metadata = joined_metadata(&inputs);
+    let transcode_request = TranscodeRequest {
+        input_path: metadata.file_path.clone(),
+        output_path: request.output_path.clone(),
+        preset: request.preset,
+        segment: None,
+        segment_list: None,
+        options: request.options.clone(),
+        concat: Some(ConcatSources { inputs, list_path, audio_list_paths }),
+    };
+    Ok((transcode_request, metadata))
+}
+
+/// Metadata describing `inputs` played one after another: the first input's
+/// streams and timecode over the combined duration
+pub fn joined_metadata(inputs: &[MediaMetadata]) -> MediaMetadata {
+    let first = &inputs[0];
+    MediaMetadata {
+        file_path: first.file_path.clone(),
+        duration_sec: inputs.iter().map(|input| input.duration_sec).sum(),
+        video: first.video.clone().map(|mut video| {
+            video.is_vfr = inputs
+                .iter()
+                .any(|input| input.video.as_ref().is_some_and(|video| video.is_vfr));
+            video
+        }),
+        audio_streams: first.audio_streams.clone(),
+        timecode: first.timecode.clone(),
+        audio_files: first.audio_files.clone(),
+    }
+}
+
+/// Differences between each input and the first that rule out a stream copy,
+/// such as "input 2 is 1280x720, not 1920x1080". Fails when the inputs cannot
+/// be joined at all.
+pub fn differences(inputs: &[MediaMetadata]) -> Result<Vec<String>, TranscodeError> {
+    let first = inputs
+        .first()
+        .ok_or_else(|| TranscodeError::InvalidInput("No files provided".to_string()))?;
+    let mut differences = Vec::new();
+
+    for (index, input) in inputs.iter().enumerate().skip(1) {
+        let number = index + 1;
+        match (&first.video, &input.video) {
+            (Some(expected), Some(video)) => {
+                if video.codec != expected.codec {
+                    differences.push(format!(
+                        "input {} is {}, not {}",
+                        number, video.codec, expected.codec
+                    ));
+                }
+                if (video.display_width, video.display_height)
+                    != (expected.display_width, expected.display_height)
+                {
+                    differences.push(format!(
+                        "input {} is {}x{}, not {}x{}",
+                        number,
+                        video.display_width,
+                        video.display_height,
+                        expected.display_width,
+                        expected.display_height
+                    ));
+                }
+                if FrameRate::parse(&video.framerate) != FrameRate::parse(&expected.framerate) {
+                    differences.push(format!(
+                        "input {} runs at {} fps, not {}",
+                        number, video.framerate, expected.framerate
+                    ));
+                }
+                if video.pix_fmt != expected.pix_fmt {
+                    differences.push(format!(
+                        "input {} is {}, not {}",
+                        number, video.pix_fmt, expected.pix_fmt
+                    ));
+                }
+            }
+            (None, None) => {}
+            _ => {
+                return Err(TranscodeError::InvalidInput(format!(
+                    "{} and {} cannot be joined, only one of them has video",
+                    first.file_path, input.file_path
+                )))
+            }
+        }
+
+        if input.audio_streams.len() != first.audio_streams.len() {
+            return Err(TranscodeError::InvalidInput(format!(
+                "{} has {} audio stream(s) but {} has {}",
+                input.file_path,
+                input.audio_streams.len(),
+                first.file_path,
+                first.audio_streams.len()
+            )));
+        }
+        for (stream, expected) in input.audio_streams.iter().zip(&first.audio_streams) {
+            if (&stream.codec, stream.sample_rate, stream.channels)
+                != (&expected.codec, expected.sample_rate, expected.channels)
+            {
+                differences.push(format!(
+                    "audio {} of input {} is {} {} Hz {} ch, not {} {} Hz {} ch",
+                    stream.index,
+                    number,
+                    stream.codec,
+                    stream.sample_rate,
+                    stream.channels,
+                    expected.codec,
+                    expected.sample_rate,
+                    expected.channels
+                ));
+            }
+        }
+    }
+    Ok(differences)
+}
+
+/// Clockwise display rotation of an input's picture
+fn input_rotation(input: &MediaMetadata) -> u32 {
+    input.video.as_ref().map_or(0, |video| video.rotation)
+}
+
+/// Write an ffconcat list of the files to the temp directory
+fn write_list<'a>(paths: impl Iterator<Item = &'a str>) -> Result<String, TranscodeError> {
+    let mut list = String::from("ffconcat version 1.0\n");
+    for path in paths {
+        let path = Path::new(path);
+        let path = path.canonicalize().unwrap_or_else(|_| path.to_path_buf());
+        // Inside single quotes only the quote itself needs escaping
+        let quoted = path.to_string_lossy().replace('\'', "'\\''");
+        list.push_str(&format!("file '{}'\n", quoted));
+    }
+
+    let list_path = std::env::temp_dir().join(format!("concat-{}.ffconcat", uuid::Uuid::new_v4()));
+    std::fs::write(&list_path, list)?;
+    Ok(list_path.to_string_lossy().to_string())
+}
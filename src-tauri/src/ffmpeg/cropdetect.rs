@@ -0,0 +1,98 @@
+use crate::error::TranscodeError;
+use crate::ffmpeg::ffprobe;
+use crate::ffmpeg::SpawnNoConsole;
+use crate::models::CropRect;
+use std::process::Command;
+
+/// Relative positions in the clip where cropdetect is sampled
+const SAMPLE_POINTS: [f64; 5] = [0.1, 0.3, 0.5, 0.7, 0.9];
+
+/// Frames analysed at each sample point
+const FRAMES_PER_SAMPLE: &str = "12";
+
+/// Detect black bars by running cropdetect at several points of the file.
+/// Returns the union of the per-sample rectangles, so a dark scene at one
+/// sample point cannot cut picture that is visible elsewhere.
+pub async fn detect_crop(file_path: &str) -> Result<CropRect, TranscodeError> {
+    let metadata = ffprobe::extract_metadata(file_path).await?;
+    if metadata.video.is_none() {
+        return Err(TranscodeError::InvalidInput(format!("no video stream in {}", file_path)));
+    }
+
+    let ffmpeg_path = crate::ffmpeg::locator::get_ffmpeg_path()
+        .map_err(TranscodeError::FfmpegNotFound)?;
+
+    let sample_times: Vec<f64> = if metadata.duration_sec > 2.0 {
+        SAMPLE_POINTS
+            .iter()
+            .map(|point| metadata.duration_sec * point)
+            .collect()
+    } else {
+        vec![0.0]
+    };
+
+    let mut union: Option<CropRect> = None;
+    for time in sample_times {
+        let output = Command::new(&ffmpeg_path)
+            .args([
+                "-hide_banner",
+                "-nostats",
+                "-ss",
+                &format!("{:.3}", time),
+                "-i",
+                file_path,
+                "-frames:v",
+                FRAMES_PER_SAMPLE,
+                "-an",
+                "-vf",
+                "cropdetect=limit=24:round=2:reset=0",
+                "-f",
+                "null",
+                "-",
+            ])
+            .output_no_console()?;
+
+        // The last line of each sample has seen every analysed frame
+        let stderr = String::from_utf8_lossy(&output.stderr);
+        if let Some(rect) = stderr.lines().rev().find_map(parse_cropdetect_line) {
+            union = Some(match union {
+                Some(current) => union_rect(current, rect),
+                None => rect,
+            });
+        }
+    }
+
+    union.ok_or_else(|| TranscodeError::MediaInfoFailed("cropdetect found no picture".to_string()))
+}
+
+/// Parse "crop=W:H:X:Y" from a cropdetect log line
+fn parse_cropdetect_line(line: &str) -> Option<CropRect> {
+    let value = line.split("crop=").nth(1)?.split_whitespace().next()?;
+    let parts: Vec<u32> = value
+        .split(':')
+        .map(|s| s.parse().ok())
+        .collect::<Option<_>>()?;
+    if parts.len() != 4 || parts[0] == 0 || parts[1] == 0 {
+        return None;
+    }
+    Some(CropRect {
+        width: parts[0],
+        height: parts[1],
+        x: parts[2],
+        y: parts[3],
+    })
+}
+
+/// Smallest rectangle containing both
+fn union_rect(a: CropRect, b: CropRect) -> CropRect {
+    let x = a.x.min(b.x);
+    let y = a.y.min(b.y);
+    let right = (a.x + a.width).max(b.x + b.width);
+    let bottom = (a.y + a.height).max(b.y + b.height);
+    CropRect {
+        x,
+        y,
+        width: right - x,
+        height: bottom - y,
+    }
+}
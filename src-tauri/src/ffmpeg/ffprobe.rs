@@ -1,16 +1,49 @@
+use crate::card;
 use crate::error::TranscodeError;
 use crate::ffmpeg::SpawnNoConsole;
-use crate::models::{AudioStream, MediaMetadata, VideoStream};
+use crate::models::{AudioStream, HdrFormat, HdrMetadata, MediaMetadata, VideoStream};
+use crate::timecode::{parse_framerate, Timecode};
 use serde_json::Value;
+use std::path::Path;
 use std::process::Command;
 
+/// How far before a seek point to look for its keyframe
+const KEYFRAME_WINDOW_SEC: f64 = 30.0;
+
 pub async fn extract_metadata(
     file_path: &str,
 ) -> Result<MediaMetadata, TranscodeError> {
     let ffprobe_path = crate::ffmpeg::locator::get_ffprobe_path()
         .map_err(|e| TranscodeError::FfprobeNotFound(e))?;
+    let mut metadata = probe_streams(&ffprobe_path, file_path)?;
 
-    let output = Command::new(&ffprobe_path)
+    // Phone HEVC usually carries HDR metadata in SEI messages rather than the
+    // container, so it only shows up as side data on the decoded frames
+    if let Some(video) = metadata.video.as_mut() {
+        if video.hdr_format.is_some() && video.hdr_metadata.is_none() {
+            video.hdr_metadata = probe_frame_hdr_metadata(&ffprobe_path, file_path);
+        }
+    }
+
+    // P2 pictures have no sound; each channel is a file of its own beside them
+    if metadata.audio_streams.is_empty() {
+        for audio_file in card::p2_audio_files(Path::new(file_path)) {
+            let audio_file = audio_file.to_string_lossy().to_string();
+            let audio = probe_streams(&ffprobe_path, &audio_file)?;
+            if let Some(mut stream) = audio.audio_streams.into_iter().next() {
+                stream.index = metadata.audio_files.len() as u32;
+                metadata.audio_streams.push(stream);
+                metadata.audio_files.push(audio_file);
+            }
+        }
+    }
+
+    Ok(metadata)
+}
+
+/// Run ffprobe on one file and parse its streams
+fn probe_streams(ffprobe_path: &Path, file_path: &str) -> Result<MediaMetadata, TranscodeError> {
+    let output = Command::new(ffprobe_path)
         .args([
             "-hide_banner",
             "-loglevel",
@@ -33,6 +66,130 @@ pub async fn extract_metadata(
     parse_ffprobe_output(json, file_path)
 }
 
+/// Classify a clip as VFR by scanning video packet timestamps over the first 30 seconds.
+/// Slower than the rate comparison in `extract_metadata`, but catches VFR clips whose
+/// container reports matching real and average rates.
+pub async fn scan_vfr(file_path: &str) -> Result<bool, TranscodeError> {
+    let ffprobe_path = crate::ffmpeg::locator::get_ffprobe_path()
+        .map_err(TranscodeError::FfprobeNotFound)?;
+
+    let output = Command::new(&ffprobe_path)
+        .args([
+            "-hide_banner",
+            "-loglevel",
+            "fatal",
+            "-select_streams",
+            "v:0",
+            "-read_intervals",
+            "%+30",
+            "-show_entries",
+            "packet=pts_time",
+            "-print_format",
+            "csv=p=0",
+            file_path,
+        ])
+        .output_no_console()?;
+
+    if !output.status.success() {
+        return Err(TranscodeError::MediaInfoFailed(
+            String::from_utf8_lossy(&output.stderr).to_string(),
+        ));
+    }
+
+    // Packets arrive in decode order, so sort to presentation order first
+    let mut pts: Vec<f64> = String::from_utf8_lossy(&output.stdout)
+        .lines()
+        .filter_map(|line| line.trim().trim_end_matches(',').parse().ok())
+        .collect();
+    pts.sort_by(|a, b| a.total_cmp(b));
+
+    let mut deltas: Vec<f64> = pts.windows(2).map(|w| w[1] - w[0]).filter(|d| *d > 0.0).collect();
+    if deltas.len() < 2 {
+        return Ok(false);
+    }
+    deltas.sort_by(|a, b| a.total_cmp(b));
+
+    // CFR clips have (almost) identical frame durations; allow 10% jitter around the median
+    let median = deltas[deltas.len() / 2];
+    let outliers = deltas
+        .iter()
+        .filter(|d| (*d - median).abs() > median * 0.1)
+        .count();
+    Ok(outliers * 100 > deltas.len())
+}
+
+/// Presentation time of the last video keyframe at or before `seconds`, the
+/// point a stream copy seeking to `seconds` really starts from.
+/// None when the file has no keyframe flags to go by.
+pub async fn keyframe_before(file_path: &str, seconds: f64) -> Result<Option<f64>, TranscodeError> {
+    let ffprobe_path = crate::ffmpeg::locator::get_ffprobe_path()
+        .map_err(TranscodeError::FfprobeNotFound)?;
+
+    // Reading starts from the keyframe before the interval, so a window longer
+    // than any normal GOP is enough
+    let output = Command::new(&ffprobe_path)
+        .args([
+            "-hide_banner",
+            "-loglevel",
+            "fatal",
+            "-select_streams",
+            "v:0",
+            "-read_intervals",
+            &format!("{:.6}%{:.6}", (seconds - KEYFRAME_WINDOW_SEC).max(0.0), seconds + 0.001),
+            "-show_entries",
+            "packet=pts_time,flags",
+            "-print_format",
+            "csv=p=0",
+            file_path,
+        ])
+        .output_no_console()?;
+
+    if !output.status.success() {
+        return Err(TranscodeError::MediaInfoFailed(
+            String::from_utf8_lossy(&output.stderr).to_string(),
+        ));
+    }
+
+    // Lines look like "12.345000,K__"
+    Ok(String::from_utf8_lossy(&output.stdout)
+        .lines()
+        .filter_map(|line| {
+            let (pts, flags) = line.trim().split_once(',')?;
+            let pts: f64 = pts.parse().ok()?;
+            (flags.starts_with('K') && pts <= seconds + 0.001).then_some(pts)
+        })
+        .fold(None, |last: Option<f64>, pts| Some(last.map_or(pts, |last| last.max(pts)))))
+}
+
+/// Read HDR side data from the first video frame
+fn probe_frame_hdr_metadata(ffprobe_path: &Path, file_path: &str) -> Option<HdrMetadata> {
+    let output = Command::new(ffprobe_path)
+        .args([
+            "-hide_banner",
+            "-loglevel",
+            "fatal",
+            "-select_streams",
+            "v:0",
+            "-read_intervals",
+            "%+#1",
+            "-show_frames",
+            "-show_entries",
+            "frame=side_data_list",
+            "-print_format",
+            "json",
+            file_path,
+        ])
+        .output_no_console()
+        .ok()?;
+
+    if !output.status.success() {
+        return None;
+    }
+
+    let json: Value = serde_json::from_slice(&output.stdout).ok()?;
+    parse_hdr_side_data(&json["frames"][0])
+}
+
 fn parse_ffprobe_output(
     json: Value,
     file_path: &str,
@@ -42,22 +199,37 @@ fn parse_ffprobe_output(
         .ok_or_else(|| TranscodeError::MediaInfoFailed("No streams found in output".to_string()))?;
 
     let mut video_stream = None;
-    let mut audio_stream = None;
+    let mut audio_streams = Vec::new();
+    let mut stream_timecode = None;
+    let mut tmcd_timecode = None;
 
     for stream in streams {
         let codec_type = stream["codec_type"].as_str().unwrap_or("");
 
         match codec_type {
+            // Cover art in MP3/M4A files is reported as a single-picture video stream
+            "video" if stream["disposition"]["attached_pic"].as_u64() == Some(1) => {}
             "video" => {
                 video_stream = Some(parse_video_stream(stream)?);
+                stream_timecode = stream_timecode.or_else(|| timecode_tag(stream));
             }
             "audio" => {
-                audio_stream = Some(parse_audio_stream(stream)?);
+                audio_streams.push(parse_audio_stream(stream, audio_streams.len() as u32)?);
+            }
+            // QuickTime timecode track
+            "data" if stream["codec_tag_string"].as_str() == Some("tmcd") => {
+                tmcd_timecode = tmcd_timecode.or_else(|| timecode_tag(stream));
             }
             _ => {}
         }
     }
 
+    if video_stream.is_none() && audio_streams.is_empty() {
+        return Err(TranscodeError::MediaInfoFailed(
+            "No video or audio stream found".to_string(),
+        ));
+    }
+
     let duration = json["format"]["duration"]
         .as_str()
         .and_then(|s| s.parse().ok())
@@ -66,11 +238,23 @@ fn parse_ffprobe_output(
     Ok(MediaMetadata {
         file_path: file_path.to_string(),
         duration_sec: duration,
-        video: video_stream.ok_or_else(|| TranscodeError::MediaInfoFailed("No video stream found".to_string()))?,
-        audio: audio_stream,
+        video: video_stream,
+        audio_streams,
+        timecode: tmcd_timecode
+            .or(stream_timecode)
+            .or_else(|| timecode_tag(&json["format"])),
+        audio_files: Vec::new(),
     })
 }
 
+/// Read a "timecode" tag, ignoring values that are not valid timecode
+fn timecode_tag(value: &Value) -> Option<String> {
+    value["tags"]["timecode"]
+        .as_str()
+        .filter(|tc| Timecode::parse(tc).is_some())
+        .map(|tc| tc.to_string())
+}
+
 fn parse_video_stream(stream: &Value) -> Result<VideoStream, TranscodeError> {
     let pix_fmt = stream["pix_fmt"].as_str().unwrap_or("yuv420p").to_string();
     let bit_depth = extract_bit_depth(&pix_fmt, stream);
@@ -80,26 +264,55 @@ fn parse_video_stream(stream: &Value) -> Result<VideoStream, TranscodeError> {
         .as_str()
         .unwrap_or("25/1")
         .to_string();
+    let avg_framerate = stream["avg_frame_rate"]
+        .as_str()
+        .filter(|rate| parse_framerate(rate).is_some())
+        .unwrap_or(&framerate)
+        .to_string();
+    let is_vfr = rates_differ(&framerate, &avg_framerate);
 
     // Determine chroma subsampling from pix_fmt
     let chroma_subsampling = chroma_from_pix_fmt(&pix_fmt);
 
+    // Portrait phone footage is coded landscape with a rotation flag
+    let width = stream["width"].as_u64().unwrap_or(1920) as u32;
+    let height = stream["height"].as_u64().unwrap_or(1080) as u32;
+    let rotation = extract_rotation(stream);
+    let (display_width, display_height) = if rotation % 180 == 90 {
+        (height, width)
+    } else {
+        (width, height)
+    };
+
     Ok(VideoStream {
         codec: stream["codec_name"]
             .as_str()
             .unwrap_or("unknown")
             .to_string(),
-        width: stream["width"].as_u64().unwrap_or(1920) as u32,
-        height: stream["height"].as_u64().unwrap_or(1080) as u32,
+        width,
+        height,
+        rotation,
+        display_width,
+        display_height,
         framerate,
+        avg_framerate,
+        is_vfr,
         bit_depth,
         pix_fmt,
         chroma_subsampling,
+        color_primaries: color_tag(stream, "color_primaries"),
+        color_transfer: color_tag(stream, "color_transfer"),
+        color_space: color_tag(stream, "color_space"),
+        color_range: color_tag(stream, "color_range"),
+        hdr_format: hdr_format(stream),
+        hdr_metadata: parse_hdr_side_data(stream),
     })
 }
 
-fn parse_audio_stream(stream: &Value) -> Result<AudioStream, TranscodeError> {
+fn parse_audio_stream(stream: &Value, index: u32) -> Result<AudioStream, TranscodeError> {
+    let sample_fmt = stream["sample_fmt"].as_str().unwrap_or("fltp").to_string();
     Ok(AudioStream {
+        index,
         codec: stream["codec_name"]
             .as_str()
             .unwrap_or("unknown")
@@ -109,9 +322,128 @@ fn parse_audio_stream(stream: &Value) -> Result<AudioStream, TranscodeError> {
             .and_then(|s| s.parse().ok())
             .unwrap_or(48000),
         channels: stream["channels"].as_u64().unwrap_or(2) as u8,
+        bit_depth: extract_audio_bit_depth(&sample_fmt, stream),
+        sample_fmt,
+        language: stream["tags"]["language"]
+            .as_str()
+            .filter(|s| *s != "und")
+            .map(|s| s.to_string()),
+        title: stream["tags"]["title"].as_str().map(|s| s.to_string()),
     })
 }
 
+/// Whether the real and average frame rates disagree, which indicates VFR.
+/// A 2:1 ratio is interlaced content reporting its field rate, not VFR.
+fn rates_differ(framerate: &str, avg_framerate: &str) -> bool {
+    let (Some(rate), Some(avg)) = (parse_framerate(framerate), parse_framerate(avg_framerate)) else {
+        return false;
+    };
+    let ratio = rate / avg;
+    (ratio - 1.0).abs() > 0.01 && (ratio - 2.0).abs() > 0.01
+}
+
+/// Clockwise display rotation from the display matrix side data or the legacy `rotate` tag
+fn extract_rotation(stream: &Value) -> u32 {
+    // The display matrix reports the counter-clockwise angle, e.g. -90 for portrait
+    let matrix_rotation = stream["side_data_list"].as_array().and_then(|side_data| {
+        side_data
+            .iter()
+            .find(|entry| entry["side_data_type"].as_str() == Some("Display Matrix"))
+            .and_then(|entry| entry["rotation"].as_f64())
+            .map(|degrees| -degrees)
+    });
+    let tag_rotation = stream["tags"]["rotate"]
+        .as_str()
+        .and_then(|s| s.parse::<f64>().ok());
+
+    let degrees = matrix_rotation.or(tag_rotation).unwrap_or(0.0);
+    // Normalise to 0, 90, 180 or 270
+    ((degrees / 90.0).round() as i64).rem_euclid(4) as u32 * 90
+}
+
+/// Read a colour tag, treating "unknown" the same as a missing tag
+fn color_tag(stream: &Value, key: &str) -> Option<String> {
+    stream[key]
+        .as_str()
+        .filter(|s| !s.is_empty() && *s != "unknown")
+        .map(|s| s.to_string())
+}
+
+/// Detect HDR from the transfer characteristics
+fn hdr_format(stream: &Value) -> Option<HdrFormat> {
+    match stream["color_transfer"].as_str()? {
+        "smpte2084" => Some(HdrFormat::Pq),
+        "arib-std-b67" => Some(HdrFormat::Hlg),
+        _ => None,
+    }
+}
+
+/// Parse mastering display and content light level entries from a side_data_list
+fn parse_hdr_side_data(value: &Value) -> Option<HdrMetadata> {
+    let side_data = value["side_data_list"].as_array()?;
+    let mut hdr = HdrMetadata::default();
+
+    for entry in side_data {
+        match entry["side_data_type"].as_str().unwrap_or("") {
+            "Mastering display metadata" => {
+                hdr.mastering_display = format_mastering_display(entry);
+            }
+            "Content light level metadata" => {
+                hdr.max_cll = entry["max_content"].as_u64().map(|v| v as u32);
+                hdr.max_fall = entry["max_average"].as_u64().map(|v| v as u32);
+            }
+            _ => {}
+        }
+    }
+
+    if hdr == HdrMetadata::default() {
+        None
+    } else {
+        Some(hdr)
+    }
+}
+
+/// Convert ffprobe mastering display rationals to x265 notation
+/// (chromaticity in 0.00002 units, luminance in 0.0001 cd/m2 units)
+fn format_mastering_display(entry: &Value) -> Option<String> {
+    let chroma = |key: &str| -> Option<u64> {
+        Some((parse_rational(entry[key].as_str()?)? * 50000.0).round() as u64)
+    };
+    let luminance = |key: &str| -> Option<u64> {
+        Some((parse_rational(entry[key].as_str()?)? * 10000.0).round() as u64)
+    };
+
+    Some(format!(
+        "G({},{})B({},{})R({},{})WP({},{})L({},{})",
+        chroma("green_x")?,
+        chroma("green_y")?,
+        chroma("blue_x")?,
+        chroma("blue_y")?,
+        chroma("red_x")?,
+        chroma("red_y")?,
+        chroma("white_point_x")?,
+        chroma("white_point_y")?,
+        luminance("max_luminance")?,
+        luminance("min_luminance")?,
+    ))
+}
+
+/// Parse an ffprobe rational such as "35400/50000"
+fn parse_rational(value: &str) -> Option<f64> {
+    match value.split_once('/') {
+        Some((num, den)) => {
+            let num: f64 = num.trim().parse().ok()?;
+            let den: f64 = den.trim().parse().ok()?;
+            if den == 0.0 {
+                None
+            } else {
+                Some(num / den)
+            }
+        }
+        None => value.trim().parse().ok(),
+    }
+}
+
 fn extract_bit_depth(pix_fmt: &str, stream: &Value) -> u8 {
     // First check bits_per_raw_sample
     if let Some(bits) = stream["bits_per_raw_sample"].as_u64() {
@@ -128,6 +460,27 @@ fn extract_bit_depth(pix_fmt: &str, stream: &Value) -> u8 {
     }
 }
 
+/// Source bits per sample: the raw sample size (24-bit PCM is decoded to s32),
+/// else the size of the decoded sample format
+fn extract_audio_bit_depth(sample_fmt: &str, stream: &Value) -> u8 {
+    // ffprobe reports bits_per_raw_sample as a string and bits_per_sample as a number
+    let raw_bits = stream["bits_per_raw_sample"]
+        .as_str()
+        .and_then(|s| s.parse::<u8>().ok())
+        .or_else(|| stream["bits_per_sample"].as_u64().map(|bits| bits as u8))
+        .filter(|bits| *bits > 0);
+    if let Some(bits) = raw_bits {
+        return bits;
+    }
+
+    match sample_fmt.trim_end_matches('p') {
+        "u8" => 8,
+        "s16" => 16,
+        "dbl" | "s64" => 64,
+        _ => 32, // s32 and flt
+    }
+}
+
 fn chroma_from_pix_fmt(pix_fmt: &str) -> String {
     if pix_fmt.contains("420") {
         "4:2:0".to_string()
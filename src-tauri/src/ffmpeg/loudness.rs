@@ -0,0 +1,165 @@
+use crate::error::TranscodeError;
+use crate::models::{LoudnessMeasurement, LoudnessNormalization, TimeSegment};
+use crate::preset::format_time_as_ffmpeg;
+use serde_json::Value;
+
+impl LoudnessNormalization {
+    /// Check the targets against loudnorm's accepted ranges
+    pub fn validate(&self) -> Result<(), TranscodeError> {
+        let checks = [
+            ("integrated loudness", self.integrated_lufs, -70.0, -5.0),
+            ("true peak", self.true_peak_dbtp, -9.0, 0.0),
+            ("loudness range", self.lra, 1.0, 50.0),
+        ];
+        for (name, value, min, max) in checks {
+            if !(min..=max).contains(&value) {
+                return Err(TranscodeError::InvalidOption(format!(
+                    "{} target {} must be between {} and {}",
+                    name, value, min, max
+                )));
+            }
+        }
+        Ok(())
+    }
+
+    /// Arguments for the analysis pass: decode audio only and print loudnorm's JSON report.
+    /// `inputs` are the source and extra audio inputs and `audio_streams` maps and
+    /// filters the output tracks, the first one ending with `measure_filter`.
+    pub fn measure_args(
+        &self,
+        segment: Option<&TimeSegment>,
+        inputs: Vec<String>,
+        audio_streams: Vec<String>,
+    ) -> Vec<String> {
+        let mut args = inputs;
+        if let Some(seg) = segment {
+            if let Some(end) = seg.end_sec {
+                args.push("-t".to_string());
+                args.push(format_time_as_ffmpeg((end - seg.start_sec).max(0.0)));
+            }
+        }
+
+        args.extend(audio_streams);
+        args.extend(vec![
+            "-f".to_string(),
+            "null".to_string(),
+            "-".to_string(),
+        ]);
+        args
+    }
+
+    /// Analysis filter printing the measurement as JSON
+    pub fn measure_filter(&self) -> String {
+        format!(
+            "loudnorm=I={}:TP={}:LRA={}:print_format=json",
+            self.integrated_lufs, self.true_peak_dbtp, self.lra
+        )
+    }
+
+    /// Second-pass filter applying the measured correction, None before measuring
+    /// or when the source is silent (loudnorm cannot gain up digital silence)
+    pub fn apply_filter(&self) -> Option<String> {
+        let m = self.measured.as_ref()?;
+        if !m.input_i.is_finite() || !m.input_tp.is_finite() {
+            return None;
+        }
+        Some(format!(
+            "loudnorm=I={}:TP={}:LRA={}:measured_I={}:measured_TP={}:measured_LRA={}:measured_thresh={}:offset={}:linear=true",
+            self.integrated_lufs,
+            self.true_peak_dbtp,
+            self.lra,
+            m.input_i,
+            m.input_tp,
+            m.input_lra,
+            m.input_thresh,
+            m.target_offset
+        ))
+    }
+}
+
+/// Parse the JSON block loudnorm prints at the end of the analysis pass
+pub fn parse_measurement(stderr_lines: &[String]) -> Result<LoudnessMeasurement, TranscodeError> {
+    let text = stderr_lines.join("\n");
+    let start = text.rfind('{');
+    let end = text.rfind('}');
+    let json = match (start, end) {
+        (Some(start), Some(end)) if start < end => &text[start..=end],
+        _ => {
+            return Err(TranscodeError::TranscodeFailed(
+                "loudnorm produced no measurement".to_string(),
+            ))
+        }
+    };
+
+    let value: Value = serde_json::from_str(json)?;
+    // loudnorm reports numbers as strings, "-inf" for silence
+    let field = |key: &str| -> Result<f64, TranscodeError> {
+        value[key]
+            .as_str()
+            .and_then(|s| s.trim().parse().ok())
+            .ok_or_else(|| TranscodeError::TranscodeFailed(format!("loudnorm measurement missing {}", key)))
+    };
+
+    Ok(LoudnessMeasurement {
+        input_i: field("input_i")?,
+        input_tp: field("input_tp")?,
+        input_lra: field("input_lra")?,
+        input_thresh: field("input_thresh")?,
+        target_offset: field("target_offset")?,
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn stderr(input_i: &str, input_tp: &str) -> Vec<String> {
+        format!(
+            "size=N/A time=00:01:00.00 bitrate=N/A speed= 120x\n\
+[Parsed_loudnorm_0 @ 0x600000e54000] \n\
+{{\n\
+\t\"input_i\" : \"{}\",\n\
+\t\"input_tp\" : \"{}\",\n\
+\t\"input_lra\" : \"6.50\",\n\
+\t\"input_thresh\" : \"-29.63\",\n\
+\t\"output_i\" : \"-23.02\",\n\
+\t\"output_tp\" : \"-2.00\",\n\
+\t\"output_lra\" : \"5.80\",\n\
+\t\"output_thresh\" : \"-33.11\",\n\
+\t\"normalization_type\" : \"dynamic\",\n\
+\t\"target_offset\" : \"0.02\"\n\
+}}",
+            input_i, input_tp
+        )
+        .lines()
+        .map(str::to_string)
+        .collect()
+    }
+
+    #[test]
+    fn parses_the_loudnorm_summary() {
+        let measurement = parse_measurement(&stderr("-19.52", "-0.47")).unwrap();
+        assert_eq!(measurement.input_i, -19.52);
+        assert_eq!(measurement.input_tp, -0.47);
+        assert_eq!(measurement.input_lra, 6.5);
+        assert_eq!(measurement.input_thresh, -29.63);
+        assert_eq!(measurement.target_offset, 0.02);
+    }
+
+    #[test]
+    fn parses_silence_as_negative_infinity() {
+        let measurement = parse_measurement(&stderr("-inf", "-inf")).unwrap();
+        assert_eq!(measurement.input_i, f64::NEG_INFINITY);
+        assert_eq!(measurement.input_tp, f64::NEG_INFINITY);
+    }
+
+    #[test]
+    fn fails_without_a_summary() {
+        assert!(parse_measurement(&["size=N/A time=00:01:00.00".to_string()]).is_err());
+        let missing = stderr("-19.52", "-0.47")
+            .into_iter()
+            .filter(|line| !line.contains("input_lra"))
+            .collect::<Vec<_>>();
+        assert!(parse_measurement(&missing).is_err());
+    }
+}
@@ -2,6 +2,9 @@ pub mod locator;
 pub use locator::FfmpegSource;
 pub mod validator;
 pub mod ffprobe;
+pub mod concat;
+pub mod cropdetect;
+pub mod loudness;
 pub mod transcode;
 
 /// Extension trait to spawn processes without console window on Windows
@@ -1,12 +1,34 @@
+use crate::checksum;
 use crate::error::TranscodeError;
-use crate::ffmpeg::ffprobe;
+use crate::ffmpeg::{concat, cropdetect, ffprobe, loudness};
 use crate::ffmpeg::SpawnNoConsole;
-use crate::models::{BatchProgress, TimeSegment, TranscodeProgress, TranscodeRequest};
+use crate::models::{
+    BatchProgress, BatchTranscodeRequest, ClipResult, ConcatTranscodeRequest, CropMode, Handle,
+    LoudnessMeasurement, MediaMetadata, SegmentOutput, TimeSegment, TranscodeProgress,
+    TranscodeRequest, TranscodeResult,
+};
+use crate::remux;
+use crate::segment::{format_seconds, joined_duration, numbered_output_path};
 use std::io::BufRead;
+use std::path::Path;
 use std::process::{Command, Stdio};
 use std::thread;
 use tauri::Window;
 
+/// Share of the progress bar taken by the loudness analysis pass
+const LOUDNESS_PASS_SHARE: f64 = 25.0;
+
+/// Part of a job's 0-100% progress covered by one ffmpeg pass
+#[derive(Clone, Copy)]
+struct PassRange {
+    start: f64,
+    span: f64,
+}
+
+impl PassRange {
+    const FULL: PassRange = PassRange { start: 0.0, span: 100.0 };
+}
+
 /// Progress event type for different transcode modes
 enum ProgressMode {
     Single,
@@ -38,6 +60,70 @@ pub fn spawn_batch_transcode_job(
     Ok(())
 }
 
+/// Spawn one job per file of a batch, returning the batch ID
+pub fn spawn_batch(
+    request: BatchTranscodeRequest,
+    window: Window,
+) -> Result<String, TranscodeError> {
+    // Generate a batch ID
+    let batch_id = uuid::Uuid::new_v4().to_string();
+    let total_files = request.input_paths.len();
+
+    // Start transcode job for each file in parallel
+    for (index, (input_path, output_path)) in request.input_paths.iter().zip(request.output_paths.iter()).enumerate() {
+        let file_index = index;
+        let total = total_files;
+        let batch_id_clone = batch_id.clone();
+        // Window is internally reference-counted; cloning is cheap here.
+        let window_clone = window.clone();
+
+        // Get the segment for this file (if any)
+        let segment = request.segments.as_ref()
+            .and_then(|segments| segments.get(index)
+            .and_then(|s| s.as_ref().cloned()));
+        let segment_list = request.segment_lists.as_ref()
+            .and_then(|lists| lists.get(index)
+            .and_then(|list| list.as_ref().cloned()));
+
+        let transcode_request = TranscodeRequest {
+            input_path: input_path.clone(),
+            output_path: output_path.clone(),
+            preset: request.preset,
+            segment,
+            segment_list,
+            options: request.options.clone(),
+            concat: None,
+        };
+
+        // Spawn each file in its own thread
+        spawn_batch_transcode_job(
+            batch_id_clone,
+            file_index,
+            total,
+            transcode_request,
+            window_clone,
+        )?;
+    }
+
+    Ok(batch_id)
+}
+
+/// Spawn a job joining several files into one output, reported like a single transcode
+pub fn spawn_concat_job(
+    request: ConcatTranscodeRequest,
+    window: Window,
+) -> Result<(), TranscodeError> {
+    thread::spawn(move || match execute_concat(&request, &window, &ProgressMode::Single) {
+        Ok(summary) => {
+            let _ = window.emit("transcode-complete", summary);
+        }
+        Err(e) => {
+            let _ = window.emit("transcode-error", (request.input_paths[0].clone(), e.to_string()));
+        }
+    });
+    Ok(())
+}
+
 /// Core spawn function that handles both single and batch transcode
 fn spawn_transcode_with_mode(request: TranscodeRequest, window: Window, mode: ProgressMode) {
     thread::spawn(move || {
@@ -46,24 +132,31 @@ fn spawn_transcode_with_mode(request: TranscodeRequest, window: Window, mode: Pr
         // Emit completion event based on mode
         match mode {
             ProgressMode::Single => {
-                let event = if result.is_ok() {
-                    "transcode-complete"
-                } else {
-                    "transcode-error"
-                };
-                let _ = window.emit(event, request.input_path);
+                match result {
+                    Ok(summary) => {
+                        let _ = window.emit("transcode-complete", summary);
+                    }
+                    Err(e) => {
+                        let _ = window.emit("transcode-error", (request.input_path, e.to_string()));
+                    }
+                }
             }
             ProgressMode::Batch {
                 batch_id,
                 file_index,
                 ..
             } => {
-                let event = if result.is_ok() {
-                    "batch-transcode-complete"
-                } else {
-                    "batch-transcode-error"
-                };
-                let _ = window.emit(event, (batch_id, file_index));
+                match result {
+                    Ok(summary) => {
+                        let _ = window.emit("batch-transcode-complete", (batch_id, file_index, summary));
+                    }
+                    Err(e) => {
+                        let _ = window.emit(
+                            "batch-transcode-error",
+                            (batch_id, file_index, e.to_string()),
+                        );
+                    }
+                }
             }
         }
     });
@@ -74,33 +167,426 @@ fn execute_transcode(
     request: &TranscodeRequest,
     window: &Window,
     mode: &ProgressMode,
-) -> Result<(), TranscodeError> {
+) -> Result<TranscodeResult, TranscodeError> {
     // Get metadata for duration calculation
     let rt = tokio::runtime::Runtime::new()?;
     let metadata = rt
         .block_on(ffprobe::extract_metadata(&request.input_path))
         .map_err(|e| TranscodeError::MediaInfoFailed(e.to_string()))?;
+    let source_paths = [vec![request.input_path.clone()], metadata.audio_files.clone()].concat();
+    with_checksums(request, source_paths, || {
+        transcode_source(request, metadata, &rt, window, mode)
+    })
+}
 
-    let segment = request.segment.as_ref().map(|seg| {
-        if seg.is_valid(metadata.duration_sec) {
-            seg.clone()
-        } else {
-            seg.clamp(metadata.duration_sec)
-        }
+/// Probe and match up the inputs of a concat job, then transcode them as one source
+fn execute_concat(
+    request: &ConcatTranscodeRequest,
+    window: &Window,
+    mode: &ProgressMode,
+) -> Result<TranscodeResult, TranscodeError> {
+    let rt = tokio::runtime::Runtime::new()?;
+    let (request, metadata) = rt.block_on(concat::prepare(request))?;
+    let input_paths = request
+        .concat
+        .iter()
+        .flat_map(|concat| &concat.inputs)
+        .flat_map(|input| std::iter::once(&input.file_path).chain(&input.audio_files))
+        .cloned()
+        .collect();
+    let result = with_checksums(&request, input_paths, || {
+        transcode_source(&request, metadata, &rt, window, mode)
     });
 
-    // Build ffmpeg command from preset (with segment support)
-    let args = request
-        .preset
-        .build_ffmpeg_args(&metadata, &request.output_path, segment.as_ref());
+    // The demuxer lists are only read while ffmpeg runs
+    for list_path in request.concat.iter().flat_map(|concat| concat.list_paths()) {
+        let _ = std::fs::remove_file(list_path);
+    }
+    result
+}
+
+/// Run `transcode`, hashing the sources while ffmpeg reads them and the
+/// outputs as soon as they are written, while both are in the page cache
+fn with_checksums(
+    request: &TranscodeRequest,
+    source_paths: Vec<String>,
+    transcode: impl FnOnce() -> Result<TranscodeResult, TranscodeError>,
+) -> Result<TranscodeResult, TranscodeError> {
+    let Some(options) = request.options.checksum.clone() else {
+        return transcode();
+    };
+    let algorithm = options.algorithm;
+    let sources = thread::spawn(move || checksum::hash_files(&source_paths, algorithm));
+
+    let mut result = transcode()?;
+    let mut output_paths: Vec<String> =
+        result.clips.iter().map(|clip| clip.output_path.clone()).collect();
+    // Joined segments share one output
+    output_paths.dedup();
+    if output_paths.is_empty() {
+        output_paths.push(result.output_path.clone());
+    }
+    let outputs = checksum::hash_files(&output_paths, algorithm)?;
+    let sources = sources.join().map_err(|_| {
+        TranscodeError::TranscodeFailed("source checksum thread panicked".to_string())
+    })??;
+
+    let output_folder = Path::new(&result.output_path).parent().unwrap_or(Path::new(""));
+    options.write(&sources, &outputs, output_folder)?;
+    result.checksums = [sources, outputs].concat();
+    Ok(result)
+}
+
+/// Check the options against the probed source, then run every ffmpeg pass
+fn transcode_source(
+    request: &TranscodeRequest,
+    metadata: MediaMetadata,
+    rt: &tokio::runtime::Runtime,
+    window: &Window,
+    mode: &ProgressMode,
+) -> Result<TranscodeResult, TranscodeError> {
+    // Requested audio tracks must exist in this file
+    if let Some(missing) = request
+        .options
+        .audio_tracks
+        .as_deref()
+        .unwrap_or_default()
+        .iter()
+        .find(|index| **index as usize >= metadata.audio_streams.len())
+    {
+        return Err(TranscodeError::InvalidOption(format!(
+            "audio track {} does not exist, {} has {} audio stream(s)",
+            missing,
+            request.input_path,
+            metadata.audio_streams.len()
+        )));
+    }
+
+    // The audio files are read as inputs of their own, lined up with the picture
+    if !metadata.audio_files.is_empty() && request.options.audio_offset_ms.is_some_and(|ms| ms != 0)
+    {
+        return Err(TranscodeError::InvalidOption(format!(
+            "{} records its audio to separate files, which cannot be offset",
+            request.input_path
+        )));
+    }
+
+    // A segment list replaces the single segment
+    let single_segment = request.segment.as_ref().filter(|_| request.segment_list.is_none());
+    let (mut segment, mut segment_corrections) = match single_segment {
+        Some(seg) => {
+            let (seg, corrections) = seg.effective(&metadata)?;
+            (Some(seg), corrections)
+        }
+        None => (None, Vec::new()),
+    };
+
+    // Segment lists are resolved and clamped one by one, corrections numbered from 1
+    let mut segment_list = Vec::new();
+    for (index, seg) in request.segment_list.iter().flatten().enumerate() {
+        let (seg, corrections) = seg.effective(&metadata).map_err(|e| {
+            TranscodeError::InvalidSegment(format!("segment {}: {}", index + 1, e))
+        })?;
+        segment_corrections.extend(
+            corrections
+                .into_iter()
+                .map(|correction| format!("segment {}: {}", index + 1, correction)),
+        );
+        segment_list.push(seg);
+    }
+
+    // Stream copies start on the keyframe at or before the in-point, so move the
+    // in-point there, count the extra lead-in as handle and report it
+    if request.preset.is_remux() && metadata.video.is_some() {
+        let single = segment.iter_mut().map(|seg| (String::new(), seg));
+        let list = segment_list
+            .iter_mut()
+            .enumerate()
+            .map(|(index, seg)| (format!("segment {}: ", index + 1), seg));
+        for (prefix, seg) in single.chain(list) {
+            let keyframe =
+                rt.block_on(ffprobe::keyframe_before(&request.input_path, seg.start_sec))?;
+            if let Some(keyframe) = keyframe.filter(|keyframe| *keyframe < seg.start_sec) {
+                let applied = match seg.handle_before {
+                    Some(Handle::Seconds(seconds)) => seconds,
+                    _ => 0.0,
+                };
+                let handle = applied + seg.start_sec - keyframe;
+                segment_corrections.push(format!(
+                    "{}start {} s snapped to the keyframe at {} s, handle before now {} s",
+                    prefix,
+                    format_seconds(seg.start_sec),
+                    format_seconds(keyframe),
+                    format_seconds(handle)
+                ));
+                seg.start_sec = keyframe;
+                seg.handle_before = Some(Handle::Seconds(handle));
+            }
+        }
+    }
+
+    let segment_output = request.options.segment_output.unwrap_or(SegmentOutput::Numbered);
+    if let Some(crossfade) = segment_output.crossfade() {
+        let shortest = segment_list
+            .iter()
+            .map(|seg| seg.duration(metadata.duration_sec))
+            .fold(f64::INFINITY, f64::min);
+        if segment_list.len() > 1 && crossfade >= shortest {
+            return Err(TranscodeError::InvalidSegment(format!(
+                "crossfade {} s is not shorter than the shortest segment ({:.3} s)",
+                crossfade, shortest
+            )));
+        }
+    }
+
+    // Probe the external recording and put its offset in seconds
+    let mut request = request.clone();
+    if let Some(external) = request.options.external_audio.as_mut() {
+        let external_metadata = rt
+            .block_on(ffprobe::extract_metadata(&external.path))
+            .map_err(|e| TranscodeError::MediaInfoFailed(e.to_string()))?;
+        external.stream = external_metadata.audio_streams.into_iter().next();
+        if external.stream.is_none() {
+            return Err(TranscodeError::InvalidOption(format!(
+                "external audio has no audio stream: {}",
+                external.path
+            )));
+        }
+        external.resolve_offset(metadata.video.as_ref())?;
+    }
+    let audio_sources = request.preset.audio_source_streams(&metadata, &request);
+
+    // Channel remapping is checked against the channels of all audio sources
+    let output_tracks = match &request.options.audio_layout {
+        Some(layout) if !audio_sources.is_empty() => {
+            let channels = audio_sources.iter().map(|track| track.channels as u32).sum();
+            layout.check_channels(channels)?;
+            layout.output_tracks(channels)
+        }
+        _ => audio_sources.len(),
+    };
+
+    // Audio presets extract the sound of video inputs; video presets need a picture
+    if request.preset.is_audio_only() {
+        if audio_sources.is_empty() {
+            return Err(TranscodeError::InvalidInput(format!(
+                "no audio stream to extract in {}",
+                request.input_path
+            )));
+        }
+        if output_tracks > 1 && !request.preset.is_multi_track() {
+            return Err(TranscodeError::InvalidOption(format!(
+                "{} output audio tracks but WAV and FLAC hold a single track",
+                output_tracks
+            )));
+        }
+    } else if request.preset.is_remux() {
+        remux::check_streams(&metadata, &request)?;
+    } else if metadata.video.is_none() {
+        return Err(TranscodeError::InvalidInput(format!(
+            "{} has no video stream, choose an audio preset",
+            request.input_path
+        )));
+    }
+    let has_audio = !audio_sources.is_empty();
+
+    // Resolve auto crop to a fixed rectangle for this file
+    match request.options.crop {
+        _ if request.preset.is_audio_only() => {}
+        Some(CropMode::Auto) => {
+            let rect = rt.block_on(cropdetect::detect_crop(&request.input_path))?;
+            request.options.crop = Some(CropMode::Manual(rect));
+        }
+        Some(CropMode::Manual(rect)) => {
+            if let Some(video) = &metadata.video {
+                if rect.x + rect.width > video.display_width
+                    || rect.y + rect.height > video.display_height
+                {
+                    return Err(TranscodeError::InvalidOption(format!(
+                        "crop {}x{}+{}+{} is outside the {}x{} frame",
+                        rect.width,
+                        rect.height,
+                        rect.x,
+                        rect.y,
+                        video.display_width,
+                        video.display_height
+                    )));
+                }
+            }
+        }
+        None => {}
+    }
 
     // Get ffmpeg path
     let ffmpeg_path = crate::ffmpeg::locator::get_ffmpeg_path()
         .map_err(|e| TranscodeError::FfmpegNotFound(e))?;
 
+    let mut result = TranscodeResult {
+        input_path: request.input_path.clone(),
+        output_path: request.output_path.clone(),
+        loudness: None,
+        segment: segment.clone(),
+        segment_corrections,
+        clips: Vec::new(),
+        checksums: Vec::new(),
+        warnings: request.preset.hdr_metadata_warning(&metadata, &request).into_iter().collect(),
+    };
+    let encode = EncodeJob { metadata: &metadata, has_audio, window, mode };
+
+    if segment_list.is_empty() {
+        let duration = segment
+            .as_ref()
+            .map(|seg| seg.duration(metadata.duration_sec))
+            .unwrap_or(metadata.duration_sec);
+        result.loudness =
+            encode.run(&ffmpeg_path, request, segment.as_ref(), duration, PassRange::FULL)?;
+        return Ok(result);
+    }
+
+    match segment_output {
+        // Each segment is its own job, sharing the progress bar by length
+        SegmentOutput::Numbered => {
+            let total: f64 = segment_list
+                .iter()
+                .map(|seg| seg.duration(metadata.duration_sec))
+                .sum();
+            let mut done = 0.0;
+            for (index, seg) in segment_list.iter().enumerate() {
+                let duration = seg.duration(metadata.duration_sec);
+                let output_path =
+                    numbered_output_path(&request.output_path, index, segment_list.len());
+                let mut clip_request = request.clone();
+                clip_request.output_path = output_path.clone();
+                let range = PassRange {
+                    start: done / total * 100.0,
+                    span: duration / total * 100.0,
+                };
+                let loudness = encode.run(&ffmpeg_path, clip_request, Some(seg), duration, range)?;
+                result.clips.push(ClipResult {
+                    output_path,
+                    segment: seg.clone(),
+                    loudness,
+                });
+                done += duration;
+            }
+        }
+        // The filter graphs cut and join the segments in a single job
+        SegmentOutput::Joined { .. } => {
+            let duration = joined_duration(
+                &segment_list,
+                segment_output.crossfade(),
+                metadata.duration_sec,
+            );
+            request.segment_list = Some(segment_list.clone());
+            result.loudness =
+                encode.run(&ffmpeg_path, request.clone(), None, duration, PassRange::FULL)?;
+            result.clips = segment_list
+                .into_iter()
+                .map(|seg| ClipResult {
+                    output_path: request.output_path.clone(),
+                    segment: seg,
+                    loudness: None,
+                })
+                .collect();
+        }
+    }
+
+    Ok(result)
+}
+
+/// One output of a job, with the source probed and the request resolved
+struct EncodeJob<'a> {
+    metadata: &'a MediaMetadata,
+    has_audio: bool,
+    window: &'a Window,
+    mode: &'a ProgressMode,
+}
+
+impl EncodeJob<'_> {
+    /// Measure loudness if asked, then encode. `duration` is the output length
+    /// progress is measured against. Returns the loudness measurement.
+    fn run(
+        &self,
+        ffmpeg_path: &Path,
+        mut request: TranscodeRequest,
+        segment: Option<&TimeSegment>,
+        duration: f64,
+        range: PassRange,
+    ) -> Result<Option<LoudnessMeasurement>, TranscodeError> {
+        let metadata = self.metadata;
+
+        // Loudness normalisation: measure first, then encode with the linear correction
+        let mut encode_range = range;
+        if self.has_audio {
+            if let Some(target) = request.options.loudness.clone() {
+                let inputs = [
+                    request.preset.source_input_args(metadata, &request, segment),
+                    request.preset.audio_input_args(&request, segment),
+                ]
+                .concat();
+                let audio_streams = request.preset.audio_stream_args(
+                    metadata,
+                    &request,
+                    segment,
+                    &[target.measure_filter()],
+                );
+                let measure_args = target.measure_args(segment, inputs, audio_streams);
+                let measure_range = PassRange {
+                    start: range.start,
+                    span: range.span * LOUDNESS_PASS_SHARE / 100.0,
+                };
+                let stderr_lines = run_ffmpeg_pass(
+                    ffmpeg_path,
+                    &measure_args,
+                    metadata,
+                    duration,
+                    self.window,
+                    self.mode,
+                    measure_range,
+                )?;
+
+                let measured = loudness::parse_measurement(&stderr_lines)?;
+                if let Some(target) = request.options.loudness.as_mut() {
+                    target.measured = Some(measured);
+                }
+                encode_range = PassRange {
+                    start: range.start + measure_range.span,
+                    span: range.span - measure_range.span,
+                };
+            }
+        }
+
+        // Build ffmpeg command from preset (with segment support)
+        let args = request.preset.build_ffmpeg_args(metadata, &request, segment);
+
+        run_ffmpeg_pass(
+            ffmpeg_path,
+            &args,
+            metadata,
+            duration,
+            self.window,
+            self.mode,
+            encode_range,
+        )?;
+
+        Ok(request.options.loudness.and_then(|target| target.measured))
+    }
+}
+
+/// Run one ffmpeg pass, emitting progress scaled into `range`.
+/// Returns the non-progress stderr lines, which carry filter reports such as loudnorm's.
+fn run_ffmpeg_pass(
+    ffmpeg_path: &Path,
+    args: &[String],
+    metadata: &MediaMetadata,
+    duration: f64,
+    window: &Window,
+    mode: &ProgressMode,
+    range: PassRange,
+) -> Result<Vec<String>, TranscodeError> {
     // Spawn ffmpeg with stderr piped for progress parsing (no console window)
-    let mut child = Command::new(&ffmpeg_path)
-        .args(&args)
+    let mut child = Command::new(ffmpeg_path)
+        .args(args)
         .stderr(Stdio::piped())
         .spawn_no_console()
         .map_err(|e| TranscodeError::TranscodeFailed(e.to_string()))?;
@@ -111,12 +597,19 @@ fn execute_transcode(
         .take()
         .ok_or(TranscodeError::TranscodeFailed("No stderr".to_string()))?;
     let reader = std::io::BufReader::new(stderr);
+    let mut report_lines = Vec::new();
 
-    for line in reader.lines() {
-        let line = line.map_err(|e| TranscodeError::TranscodeFailed(e.to_string()))?;
+    // ffmpeg ends progress lines with '\r' and everything else with '\n'
+    for chunk in reader.split(b'\r') {
+        let chunk = chunk.map_err(|e| TranscodeError::TranscodeFailed(e.to_string()))?;
 
-        if let Some(progress) = parse_ffmpeg_progress(&line, &metadata, segment.as_ref()) {
-            emit_progress(window, mode, progress);
+        for line in String::from_utf8_lossy(&chunk).lines() {
+            if let Some(mut progress) = parse_ffmpeg_progress(line, metadata, duration) {
+                progress.progress_percent = range.start + progress.progress_percent * range.span / 100.0;
+                emit_progress(window, mode, progress);
+            } else {
+                report_lines.push(line.to_string());
+            }
         }
     }
 
@@ -130,7 +623,7 @@ fn execute_transcode(
         ));
     }
 
-    Ok(())
+    Ok(report_lines)
 }
 
 /// Emit progress event based on the current mode
@@ -159,10 +652,11 @@ fn emit_progress(window: &Window, mode: &ProgressMode, progress: TranscodeProgre
 /// Example line: frame= 123 fps=25 q=12.0 size= 12345kB time=00:00:05.00 bitrate= 1234.5kbits/s speed=1.00x
 fn parse_ffmpeg_progress(
     line: &str,
-    metadata: &crate::models::MediaMetadata,
-    segment: Option<&TimeSegment>,
+    metadata: &MediaMetadata,
+    duration: f64,
 ) -> Option<TranscodeProgress> {
-    if !line.contains("frame=") {
+    // Audio-only passes report "size=" instead of "frame="
+    if !line.contains("frame=") && !line.contains("size=") {
         return None;
     }
 
@@ -170,21 +664,12 @@ fn parse_ffmpeg_progress(
     let fps = extract_value(line, "fps=").and_then(|s| s.parse().ok());
     let bitrate = extract_value(line, "bitrate=").map(|s| s.to_string());
 
-    // Calculate progress percentage based on segment duration
+    // Calculate progress percentage based on the output duration. Output time starts
+    // at zero: seeking before -i resets timestamps and joined segments are re-timed.
     let elapsed_seconds = parse_time_string(&time_str)?;
 
-    // Determine effective duration for progress calculation
-    let (segment_start, effective_duration) = if let Some(seg) = segment {
-        (seg.start_sec, seg.duration(metadata.duration_sec))
-    } else {
-        (0.0, metadata.duration_sec)
-    };
-
-    // Adjust elapsed time relative to segment start
-    let effective_elapsed = elapsed_seconds - segment_start;
-
-    let progress = if effective_duration > 0.0 {
-        ((effective_elapsed / effective_duration) * 100.0).max(0.0)
+    let progress = if duration > 0.0 {
+        ((elapsed_seconds / duration) * 100.0).max(0.0)
     } else {
         0.0
     };
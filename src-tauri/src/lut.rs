@@ -0,0 +1,126 @@
+use crate::error::TranscodeError;
+use crate::models::{LutInterpolation, LutOptions};
+use crate::preset::quote_filter_value;
+use std::path::Path;
+
+/// Largest cube size accepted by ffmpeg's lut3d filter
+const MAX_CUBE_SIZE: usize = 256;
+
+impl LutOptions {
+    /// Parse and validate the .cube file, called when the job is submitted
+    pub fn validate(&self) -> Result<(), TranscodeError> {
+        let content = std::fs::read_to_string(&self.path)
+            .map_err(|e| TranscodeError::InvalidLut(format!("{}: {}", self.path, e)))?;
+        parse_cube(&content)
+            .map(|_| ())
+            .map_err(|e| TranscodeError::InvalidLut(format!("{}: {}", self.file_name(), e)))
+    }
+
+    /// Build the lut3d filter
+    pub fn filter(&self) -> String {
+        let interp = match self.interpolation.unwrap_or(LutInterpolation::Tetrahedral) {
+            LutInterpolation::Nearest => "nearest",
+            LutInterpolation::Trilinear => "trilinear",
+            LutInterpolation::Tetrahedral => "tetrahedral",
+        };
+        format!("lut3d=file={}:interp={}", quote_filter_value(&self.path), interp)
+    }
+
+    /// LUT file name without directories, recorded in the output metadata
+    pub fn file_name(&self) -> String {
+        Path::new(&self.path)
+            .file_name()
+            .map(|name| name.to_string_lossy().to_string())
+            .unwrap_or_else(|| self.path.clone())
+    }
+}
+
+/// Parse a .cube file and return its 3D size.
+/// Checks the header keywords and that the number of RGB rows matches LUT_3D_SIZE^3.
+fn parse_cube(content: &str) -> Result<usize, String> {
+    let mut size = None;
+    let mut rows = 0usize;
+
+    for (index, line) in content.lines().enumerate() {
+        let line = line.trim();
+        if line.is_empty() || line.starts_with('#') {
+            continue;
+        }
+
+        let mut parts = line.split_whitespace();
+        match parts.next().unwrap_or("") {
+            "TITLE" | "DOMAIN_MIN" | "DOMAIN_MAX" | "LUT_3D_INPUT_RANGE" => {}
+            "LUT_1D_SIZE" => {
+                return Err("1D LUTs are not supported, expected LUT_3D_SIZE".to_string());
+            }
+            "LUT_3D_SIZE" => {
+                let value: usize = parts
+                    .next()
+                    .and_then(|s| s.parse().ok())
+                    .ok_or_else(|| format!("invalid LUT_3D_SIZE on line {}", index + 1))?;
+                if !(2..=MAX_CUBE_SIZE).contains(&value) {
+                    return Err(format!("LUT_3D_SIZE {} is outside 2-{}", value, MAX_CUBE_SIZE));
+                }
+                size = Some(value);
+            }
+            _ => {
+                if size.is_none() {
+                    return Err(format!("data on line {} before LUT_3D_SIZE", index + 1));
+                }
+                let values: Vec<f64> = line
+                    .split_whitespace()
+                    .map(|s| s.parse::<f64>())
+                    .collect::<Result<_, _>>()
+                    .map_err(|_| format!("invalid data on line {}", index + 1))?;
+                if values.len() != 3 {
+                    return Err(format!("expected 3 values on line {}", index + 1));
+                }
+                rows += 1;
+            }
+        }
+    }
+
+    let size = size.ok_or_else(|| "missing LUT_3D_SIZE".to_string())?;
+    let expected = size.pow(3);
+    if rows != expected {
+        return Err(format!(
+            "expected {} entries for size {}, found {}",
+            expected, size, rows
+        ));
+    }
+
+    Ok(size)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn cube(size: usize, rows: usize) -> String {
+        let mut content = format!("# comment\nTITLE \"test\"\nLUT_3D_SIZE {}\n", size);
+        content.push_str("DOMAIN_MIN 0 0 0\nDOMAIN_MAX 1 1 1\n\n");
+        content.push_str(&"0.0 0.5 1.0\n".repeat(rows));
+        content
+    }
+
+    #[test]
+    fn parses_a_complete_cube() {
+        assert_eq!(parse_cube(&cube(2, 8)), Ok(2));
+        assert_eq!(parse_cube(&cube(17, 17 * 17 * 17)), Ok(17));
+    }
+
+    #[test]
+    fn rejects_malformed_cubes() {
+        assert!(parse_cube(&cube(2, 7)).unwrap_err().contains("expected 8 entries"));
+        assert!(parse_cube(&cube(1, 1)).unwrap_err().contains("outside"));
+        assert!(parse_cube("0 0 0\nLUT_3D_SIZE 2\n").unwrap_err().contains("before LUT_3D_SIZE"));
+        assert!(parse_cube("LUT_1D_SIZE 1024\n").unwrap_err().contains("1D"));
+        assert!(parse_cube("LUT_3D_SIZE two\n").unwrap_err().contains("invalid LUT_3D_SIZE"));
+        assert!(parse_cube("TITLE \"empty\"\n").unwrap_err().contains("missing"));
+
+        let short_row = cube(2, 8).replacen("0.0 0.5 1.0", "0.0 0.5", 1);
+        assert!(parse_cube(&short_row).unwrap_err().contains("expected 3 values"));
+        let bad_value = cube(2, 8).replacen("0.0 0.5 1.0", "0.0 x 1.0", 1);
+        assert!(parse_cube(&bad_value).unwrap_err().contains("invalid data"));
+    }
+}
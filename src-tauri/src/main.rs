@@ -1,19 +1,35 @@
 // Prevents additional console window on Windows in release, DO NOT REMOVE!!
 #![cfg_attr(not(debug_assertions), windows_subsystem = "windows")]
 
+mod audio;
+mod burnin;
+mod card;
+mod checksum;
+mod color;
 mod commands;
 mod error;
 mod ffmpeg;
+mod lut;
 mod models;
+mod offload;
 mod preset;
+mod remux;
+mod segment;
+mod timecode;
+mod watermark;
 
 fn main() {
     tauri::Builder::default()
         .invoke_handler(tauri::generate_handler![
             commands::check_ffmpeg_available,
             commands::get_media_info,
+            commands::detect_crop,
+            commands::scan_card,
             commands::start_transcode,
             commands::start_batch_transcode,
+            commands::start_concat_transcode,
+            commands::start_offload,
+            commands::validate_segments,
         ])
         .run(tauri::generate_context!())
         .expect("error while running tauri application");
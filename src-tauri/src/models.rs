@@ -4,40 +4,62 @@ use serde::{Deserialize, Serialize};
 #[derive(Debug, Clone, Serialize, Deserialize, PartialEq)]
 pub struct TimeSegment {
     /// Start time in seconds
+    #[serde(default)]
     pub start_sec: f64,
     /// End time in seconds, None means to the end of video
     pub end_sec: Option<f64>,
+    /// Start as a frame number or source timecode, used instead of `start_sec`
+    pub start_at: Option<SegmentPosition>,
+    /// End as a frame number or source timecode, used instead of `end_sec`
+    pub end_at: Option<SegmentPosition>,
+    /// Extra material kept before the start, cut short at the start of the source
+    pub handle_before: Option<Handle>,
+    /// Extra material kept after the end, cut short at the end of the source
+    pub handle_after: Option<Handle>,
 }
 
-impl TimeSegment {
-    /// Validate if segment is within video duration
-    pub fn is_valid(&self, duration_sec: f64) -> bool {
-        if self.start_sec < 0.0 {
-            return false;
-        }
-        if self.start_sec >= duration_sec {
-            return false;
-        }
-        if let Some(end) = self.end_sec {
-            if end <= self.start_sec {
-                return false;
-            }
-            // Allow end to exceed duration slightly (ffmpeg will handle it)
-        }
-        true
-    }
+/// Handle length. Segments reported back hold the handles actually applied in
+/// seconds, and their start and end include them.
+#[derive(Debug, Clone, Serialize, Deserialize, PartialEq)]
+pub enum Handle {
+    Seconds(f64),
+    /// Frames of the source video
+    Frames(u64),
+}
+
+/// Segment point given in frames rather than seconds, converted at the source
+/// frame rate when the job starts
+#[derive(Debug, Clone, Serialize, Deserialize, PartialEq)]
+pub enum SegmentPosition {
+    /// Frame number counted from the first frame (0)
+    Frame(u64),
+    /// Source timecode such as "01:00:10:00", or "01:00:10;00" for drop-frame
+    Timecode(String),
+}
 
+impl TimeSegment {
     /// Get segment duration in seconds
     pub fn duration(&self, total_duration: f64) -> f64 {
         let end = self.end_sec.unwrap_or(total_duration);
         end.min(total_duration) - self.start_sec
     }
 
-    /// Clamp segment to be within video duration
+    /// Clamp segment to be within video duration, keeping at least 0.1 s
+    /// where the file is that long
     pub fn clamp(&self, duration_sec: f64) -> Self {
-        let start_sec = self.start_sec.clamp(0.0, duration_sec - 0.1);
-        let end_sec = self.end_sec.map(|end| end.clamp(start_sec + 0.1, duration_sec));
-        Self { start_sec, end_sec }
+        let duration_sec = duration_sec.max(0.0);
+        let start_sec = self.start_sec.clamp(0.0, (duration_sec - 0.1).max(0.0));
+        let end_sec = self
+            .end_sec
+            .map(|end| end.clamp((start_sec + 0.1).min(duration_sec), duration_sec));
+        Self {
+            start_sec,
+            end_sec,
+            start_at: None,
+            end_at: None,
+            handle_before: self.handle_before.clone(),
+            handle_after: self.handle_after.clone(),
+        }
     }
 }
 
@@ -46,26 +68,218 @@ impl TimeSegment {
 pub struct MediaMetadata {
     pub file_path: String,
     pub duration_sec: f64,
-    pub video: VideoStream,
-    pub audio: Option<AudioStream>,
+    /// None for audio-only files (WAV, MP3, BWF...)
+    pub video: Option<VideoStream>,
+    /// Every audio stream, in file order
+    pub audio_streams: Vec<AudioStream>,
+    /// Start timecode, e.g. "01:00:00:00" or "01:00:00;00" for drop-frame.
+    /// Read from the tmcd track, else the video stream, else the container.
+    pub timecode: Option<String>,
+    /// Files carrying the audio streams, one each, when the picture file has none.
+    /// P2 cards record every audio channel to its own file.
+    #[serde(default)]
+    pub audio_files: Vec<String>,
+}
+
+impl MediaMetadata {
+    /// Audio streams to output, in output order. `tracks` lists audio stream
+    /// indices to keep; None keeps every stream. Unknown indices are skipped.
+    pub fn selected_audio(&self, tracks: Option<&[u32]>) -> Vec<&AudioStream> {
+        match tracks {
+            Some(tracks) => tracks
+                .iter()
+                .filter_map(|index| self.audio_streams.get(*index as usize))
+                .collect(),
+            None => self.audio_streams.iter().collect(),
+        }
+    }
+
+    /// Stream specifier of audio stream `track` when this file is the first
+    /// input, followed by its audio files
+    pub fn audio_label(&self, track: u32) -> String {
+        match self.audio_files.is_empty() {
+            true => format!("0:a:{}", track),
+            false => format!("{}:a:0", track + 1),
+        }
+    }
 }
 
 #[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct VideoStream {
     pub codec: String,
-    pub width: u32,
-    pub height: u32,
+    pub width: u32,  // Coded width
+    pub height: u32, // Coded height
+    /// Clockwise rotation needed for display: 0, 90, 180 or 270
+    pub rotation: u32,
+    /// Width and height as displayed, after rotation
+    pub display_width: u32,
+    pub display_height: u32,
     pub framerate: String,          // Preserve as "30000/1001" for exactness
+    pub avg_framerate: String,      // Average rate, differs from framerate for VFR
+    /// Variable frame rate, from the rate mismatch or a packet timestamp scan
+    pub is_vfr: bool,
     pub bit_depth: u8,              // 8 or 10
     pub pix_fmt: String,            // "yuv420p", "yuv422p10le", etc.
     pub chroma_subsampling: String, // "4:2:0", "4:2:2"
+    /// Colour tags as reported by ffprobe, None when the stream is untagged
+    pub color_primaries: Option<String>, // "bt709", "bt2020"
+    pub color_transfer: Option<String>,  // "bt709", "smpte2084", "arib-std-b67"
+    pub color_space: Option<String>,     // "bt709", "bt2020nc"
+    pub color_range: Option<String>,     // "tv" (limited) or "pc" (full)
+    /// HDR signalling derived from the transfer characteristics, None for SDR
+    pub hdr_format: Option<HdrFormat>,
+    /// Static HDR metadata from stream or first-frame side data
+    pub hdr_metadata: Option<HdrMetadata>,
 }
 
-#[derive(Debug, Clone, Serialize, Deserialize)]
+/// HDR transfer function
+#[derive(Debug, Clone, Copy, Serialize, Deserialize, PartialEq)]
+pub enum HdrFormat {
+    /// SMPTE ST 2084 (HDR10, Dolby Vision base layer)
+    Pq,
+    /// ARIB STD-B67 Hybrid Log-Gamma
+    Hlg,
+}
+
+/// Static HDR metadata: mastering display (ST 2086) and content light levels
+#[derive(Debug, Clone, Default, Serialize, Deserialize, PartialEq)]
+pub struct HdrMetadata {
+    /// Mastering display in x265 notation, e.g. "G(13250,34500)B(7500,3000)R(34000,16000)WP(15635,16450)L(10000000,50)"
+    pub mastering_display: Option<String>,
+    /// Maximum content light level in cd/m2
+    pub max_cll: Option<u32>,
+    /// Maximum frame-average light level in cd/m2
+    pub max_fall: Option<u32>,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize, PartialEq)]
 pub struct AudioStream {
+    /// Position among the file's audio streams, as used in `0:a:N` stream specifiers
+    pub index: u32,
     pub codec: String,
     pub sample_rate: u32,
     pub channels: u8,
+    /// Decoded sample format, e.g. "s16", "s32" or "fltp" (lossy codecs decode to float)
+    pub sample_fmt: String,
+    /// Bits per sample of the source, e.g. 24 for 24-bit PCM carried in s32
+    pub bit_depth: u8,
+    pub language: Option<String>,
+    pub title: Option<String>,
+}
+
+/// Colour standard used as a conversion target
+#[derive(Debug, Clone, Copy, Serialize, Deserialize, PartialEq)]
+pub enum ColorStandard {
+    Rec601,
+    Rec709,
+    Rec2020,
+}
+
+/// Signal range used as a conversion target
+#[derive(Debug, Clone, Copy, Serialize, Deserialize, PartialEq)]
+pub enum ColorRange {
+    Limited,
+    Full,
+}
+
+/// Explicit colour-space conversion, applied through zscale
+#[derive(Debug, Clone, Serialize, Deserialize, PartialEq)]
+pub struct ColorConversion {
+    /// Target primaries, transfer and matrix - None keeps the source standard
+    pub standard: Option<ColorStandard>,
+    /// Target range - None keeps the source range
+    pub range: Option<ColorRange>,
+}
+
+/// Tone-mapping operator for HDR to Rec.709 SDR conversion
+#[derive(Debug, Clone, Copy, Serialize, Deserialize, PartialEq)]
+pub enum ToneMapping {
+    /// Keep the HDR signal untouched
+    Off,
+    Hable,
+    Mobius,
+    Reinhard,
+}
+
+/// Interpolation used when applying a 3D LUT
+#[derive(Debug, Clone, Copy, Serialize, Deserialize, PartialEq)]
+pub enum LutInterpolation {
+    Nearest,
+    Trilinear,
+    Tetrahedral,
+}
+
+/// 3D LUT (.cube) baked into the output
+#[derive(Debug, Clone, Serialize, Deserialize, PartialEq)]
+pub struct LutOptions {
+    /// Path to the .cube file
+    pub path: String,
+    /// Interpolation - None uses tetrahedral
+    pub interpolation: Option<LutInterpolation>,
+}
+
+/// Where a burn-in element is drawn
+#[derive(Debug, Clone, Copy, Serialize, Deserialize, PartialEq)]
+pub enum BurnInPosition {
+    TopLeft,
+    TopCenter,
+    TopRight,
+    BottomLeft,
+    BottomCenter,
+    BottomRight,
+}
+
+/// What a burn-in element shows
+#[derive(Debug, Clone, Serialize, Deserialize, PartialEq)]
+pub enum BurnInContent {
+    /// Source timecode, adjusted for the segment start
+    Timecode,
+    /// Source file name
+    Filename,
+    /// Source frame number
+    FrameCounter,
+    /// Free text with {filename}, {frame} and {time} tokens
+    Text(String),
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize, PartialEq)]
+pub struct BurnInElement {
+    pub content: BurnInContent,
+    pub position: BurnInPosition,
+}
+
+/// Text burned into the picture for dailies and review copies
+#[derive(Debug, Clone, Serialize, Deserialize, PartialEq)]
+pub struct BurnIn {
+    pub elements: Vec<BurnInElement>,
+    /// Text height as a fraction of the frame height - None uses 0.035
+    pub size: Option<f64>,
+    /// Background box opacity from 0.0 to 1.0 - None uses 0.5, 0.0 disables the box
+    pub box_opacity: Option<f64>,
+    /// Font file - None picks a monospace system font
+    pub font_file: Option<String>,
+}
+
+/// Where a watermark is anchored
+#[derive(Debug, Clone, Copy, Serialize, Deserialize, PartialEq)]
+pub enum WatermarkAnchor {
+    TopLeft,
+    TopRight,
+    BottomLeft,
+    BottomRight,
+    Center,
+}
+
+/// Image watermark overlaid on the picture
+#[derive(Debug, Clone, Serialize, Deserialize, PartialEq)]
+pub struct Watermark {
+    /// Path to the image (PNG with alpha recommended)
+    pub path: String,
+    pub anchor: WatermarkAnchor,
+    /// Watermark width as a fraction of the video width - None uses 0.15
+    pub scale: Option<f64>,
+    /// Opacity from 0.0 to 1.0 - None uses 1.0
+    pub opacity: Option<f64>,
 }
 
 /// Output presets for transcoding
@@ -81,10 +295,185 @@ pub enum OutputPreset {
     DnxHRHQX,
     #[serde(rename = "H264Crf18")]
     H264Crf18,
+    /// Audio only: WAV PCM 24-bit/48 kHz. Drops the picture of video inputs.
+    #[serde(rename = "WavPcm24")]
+    WavPcm24,
+    /// Audio only: FLAC at the source rate
+    #[serde(rename = "Flac")]
+    Flac,
+    /// Audio only: AAC 320 kbps in M4A
+    #[serde(rename = "Aac")]
+    Aac,
+    /// Copy the streams into the container the output extension names, without re-encoding
+    #[serde(rename = "Remux")]
+    Remux,
+}
+
+/// Crop rectangle in source pixels
+#[derive(Debug, Clone, Copy, Serialize, Deserialize, PartialEq)]
+pub struct CropRect {
+    pub x: u32,
+    pub y: u32,
+    pub width: u32,
+    pub height: u32,
+}
+
+/// How the picture is cropped
+#[derive(Debug, Clone, Copy, Serialize, Deserialize, PartialEq)]
+pub enum CropMode {
+    /// Detect black bars per file with cropdetect when the job starts
+    Auto,
+    /// Use a fixed rectangle
+    Manual(CropRect),
+}
+
+/// Two-pass loudness normalisation target, applied to the first output audio track.
+/// EBU R128 is -23 LUFS / -1 dBTP, ATSC A/85 is -24 LUFS / -2 dBTP.
+#[derive(Debug, Clone, Serialize, Deserialize, PartialEq)]
+pub struct LoudnessNormalization {
+    /// Integrated loudness target in LUFS
+    pub integrated_lufs: f64,
+    /// True-peak ceiling in dBTP
+    pub true_peak_dbtp: f64,
+    /// Loudness range target in LU
+    pub lra: f64,
+    /// First-pass measurement, filled in when the job runs
+    #[serde(skip)]
+    pub measured: Option<LoudnessMeasurement>,
+}
+
+/// Values measured by the loudnorm analysis pass
+#[derive(Debug, Clone, Serialize, Deserialize, PartialEq)]
+pub struct LoudnessMeasurement {
+    pub input_i: f64,
+    pub input_tp: f64,
+    pub input_lra: f64,
+    pub input_thresh: f64,
+    pub target_offset: f64,
+}
+
+/// Downmix coefficients for 5.1 and 7.1 sources
+#[derive(Debug, Clone, Copy, Serialize, Deserialize, PartialEq)]
+pub enum DownmixCoefficients {
+    /// ITU-R BS.775 Lo/Ro: centre and surrounds at -3 dB, LFE dropped
+    Itu,
+    /// Matrix-encoded Lt/Rt (Dolby Pro Logic II compatible)
+    LtRt,
+    /// Custom linear gains for centre, surrounds and LFE
+    Custom { center: f64, surround: f64, lfe: f64 },
+}
+
+/// How the source channels are laid out in the output tracks.
+/// Channels of the selected audio tracks are numbered in order, so two stereo
+/// tracks give channels 0-3.
+#[derive(Debug, Clone, Serialize, Deserialize, PartialEq)]
+pub enum AudioLayout {
+    /// One mono track per source channel
+    SplitMono,
+    /// All channels merged into one multichannel track
+    Merge,
+    /// Stereo downmix of a mono, stereo, 5.1 or 7.1 source
+    StereoDownmix(DownmixCoefficients),
+    /// One output track per entry, each listing its source channels
+    Custom(Vec<Vec<u32>>),
+}
+
+/// Whether external audio replaces the source audio or is added after it
+#[derive(Debug, Clone, Copy, Serialize, Deserialize, PartialEq)]
+pub enum ExternalAudioMode {
+    Replace,
+    Add,
+}
+
+/// Sync offset, in seconds or in frames of the source video
+#[derive(Debug, Clone, Copy, Serialize, Deserialize, PartialEq)]
+pub enum SyncOffset {
+    Seconds(f64),
+    Frames(i64),
+}
+
+/// Audio from a separate recorder (double-system sound)
+#[derive(Debug, Clone, Serialize, Deserialize, PartialEq)]
+pub struct ExternalAudio {
+    pub path: String,
+    pub mode: ExternalAudioMode,
+    /// Where the recording starts on the source timeline, negative if it starts
+    /// before the source - None means both start together
+    pub offset: Option<SyncOffset>,
+    /// First audio stream of the file, probed when the job starts
+    #[serde(skip)]
+    pub stream: Option<AudioStream>,
+}
+
+/// Output audio bit depth
+#[derive(Debug, Clone, Copy, Serialize, Deserialize, PartialEq)]
+pub enum AudioBitDepth {
+    Int16,
+    Int24,
+    Float32,
+}
+
+/// Output audio sample rate and bit depth, None fields use the preset default
+#[derive(Debug, Clone, Copy, Serialize, Deserialize, PartialEq)]
+pub struct AudioFormat {
+    pub sample_rate: Option<u32>,
+    pub bit_depth: Option<AudioBitDepth>,
+}
+
+/// Processing options shared by single and batch requests
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+pub struct TranscodeOptions {
+    /// Constant output frame rate, e.g. "25" or "30000/1001" - None converts
+    /// VFR sources to the nearest standard rate and leaves CFR sources alone
+    pub frame_rate: Option<String>,
+    /// Optional crop, applied before any colour processing
+    pub crop: Option<CropMode>,
+    /// Optional colour conversion - None tags the output to match the source
+    pub color_conversion: Option<ColorConversion>,
+    /// Optional HDR tone mapping - None tone-maps automatically on proxy/review presets
+    pub tone_mapping: Option<ToneMapping>,
+    /// Optional 3D LUT, applied after rotation, retiming and crop and before
+    /// any tone mapping or colour conversion
+    pub lut: Option<LutOptions>,
+    /// Optional text burn-in, drawn last in the video filter chain
+    pub burn_in: Option<BurnIn>,
+    /// Optional image watermark, overlaid before any burn-in
+    pub watermark: Option<Watermark>,
+    /// Optional two-pass loudness normalisation
+    pub loudness: Option<LoudnessNormalization>,
+    /// Audio stream indices to output, in order - None keeps every track,
+    /// an empty list drops audio
+    pub audio_tracks: Option<Vec<u32>>,
+    /// Optional channel remapping of the selected tracks - None keeps them as they are
+    pub audio_layout: Option<AudioLayout>,
+    /// Optional external recording, replacing or added to the source audio
+    pub external_audio: Option<ExternalAudio>,
+    /// Sync correction of the source audio in milliseconds: positive delays it,
+    /// negative advances it. The output keeps the video duration.
+    pub audio_offset_ms: Option<i64>,
+    /// Optional output sample rate and bit depth - None uses the preset default
+    pub audio_format: Option<AudioFormat>,
+    /// How a segment list is written - None writes numbered files
+    pub segment_output: Option<SegmentOutput>,
+    /// Optional checksums of the source and output, written beside the output
+    pub checksum: Option<ChecksumOptions>,
+}
+
+/// How the segments of a segment list are written
+#[derive(Debug, Clone, Copy, Serialize, Deserialize, PartialEq)]
+pub enum SegmentOutput {
+    /// Each segment to its own file, numbered after the output name:
+    /// "clip_01.mov", "clip_02.mov"...
+    Numbered,
+    /// The segments in order, joined into the one output
+    Joined {
+        /// Video and audio crossfade between segments in seconds, None for straight cuts
+        crossfade_sec: Option<f64>,
+    },
 }
 
 /// Transcode request from frontend
-#[derive(Debug, Serialize, Deserialize)]
+#[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct TranscodeRequest {
     pub input_path: String,
     pub output_path: String,
@@ -92,6 +481,39 @@ pub struct TranscodeRequest {
     /// Optional time segment - None means transcode the entire video
     #[serde(rename = "segment")]
     pub segment: Option<TimeSegment>,
+    /// Several segments of the input, used instead of `segment` and written
+    /// as `options.segment_output` says
+    pub segment_list: Option<Vec<TimeSegment>>,
+    /// Processing options, flattened into the request
+    #[serde(flatten)]
+    pub options: TranscodeOptions,
+    /// Inputs joined in front of the processing, resolved for concat jobs
+    /// (`input_path` is then the first of them)
+    #[serde(skip)]
+    pub concat: Option<ConcatSources>,
+}
+
+/// Concatenation request from frontend: the inputs are joined in order into one output
+#[derive(Debug, Serialize, Deserialize)]
+pub struct ConcatTranscodeRequest {
+    pub input_paths: Vec<String>,
+    pub output_path: String,
+    pub preset: OutputPreset,
+    /// Processing options applied to the joined result, flattened into the request
+    #[serde(flatten)]
+    pub options: TranscodeOptions,
+}
+
+/// Probed inputs of a concat job
+#[derive(Debug, Clone)]
+pub struct ConcatSources {
+    pub inputs: Vec<MediaMetadata>,
+    /// ffconcat list read by the concat demuxer when the inputs match stream for
+    /// stream; None normalises and joins them in the filter graph
+    pub list_path: Option<String>,
+    /// Demuxer lists joining each audio file of the inputs, when their audio is
+    /// recorded to separate files
+    pub audio_list_paths: Vec<String>,
 }
 
 /// Progress updates sent to frontend
@@ -105,6 +527,84 @@ pub struct TranscodeProgress {
     pub estimated_time: Option<String>,
 }
 
+/// Result of a finished job, sent with the completion event
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct TranscodeResult {
+    pub input_path: String,
+    pub output_path: String,
+    /// Loudness measured by the analysis pass, when normalisation ran
+    pub loudness: Option<LoudnessMeasurement>,
+    /// Segment actually transcoded, in seconds, after resolving and clamping
+    pub segment: Option<TimeSegment>,
+    /// Changes made to fit the requested segments to the source
+    pub segment_corrections: Vec<String>,
+    /// One entry per segment of a segment list, empty otherwise
+    pub clips: Vec<ClipResult>,
+    /// Hashes of every source and output, when checksums were asked for
+    pub checksums: Vec<FileChecksum>,
+    /// Source properties the output could not keep, e.g. HDR mastering metadata
+    pub warnings: Vec<String>,
+}
+
+/// A segment of a segment list as transcoded
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct ClipResult {
+    /// The numbered file, or the joined output all segments went to
+    pub output_path: String,
+    /// Segment in seconds, after resolving and clamping
+    pub segment: TimeSegment,
+    /// Loudness measured for this file, when normalisation ran on numbered files
+    pub loudness: Option<LoudnessMeasurement>,
+}
+
+/// Segment check for one file, returned by `validate_segments`
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct SegmentCheck {
+    pub input_path: String,
+    /// Segment that would be transcoded, None when there is no segment or it is rejected
+    pub segment: Option<TimeSegment>,
+    /// Changes made to fit the segment to the source, e.g. "end 3600 s clamped to 1843.2 s"
+    pub corrections: Vec<String>,
+    /// Why the segment was rejected
+    pub error: Option<String>,
+}
+
+/// Camera card layouts recognised when scanning a card
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+pub enum CardLayout {
+    /// Sony and Panasonic consumer cameras: `PRIVATE/AVCHD/BDMV/STREAM/*.MTS`
+    Avchd,
+    /// Sony XDCAM: `Clip/*.MXF`, or `BPAV/CLPR/*/*.MP4` for XDCAM EX
+    Xdcam,
+    /// Panasonic P2: `CONTENTS/VIDEO/*.MXF`
+    P2,
+    /// GoPro: `DCIM/100GOPRO`, long recordings chaptered as GX01xxxx, GX02xxxx...
+    GoPro,
+    /// DJI drones and gimbals: `DCIM/100MEDIA` or `DCIM/DJI_001`
+    Dji,
+}
+
+/// One recording found on a card. Cameras split long recordings into several
+/// files; `file_paths` lists them in order and `metadata` covers all of them.
+/// Recordings of more than one file are transcoded with `start_concat_transcode`.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct CardClip {
+    /// Name of the first file without its extension, e.g. "GX010123"
+    pub name: String,
+    pub layout: CardLayout,
+    pub file_paths: Vec<String>,
+    pub metadata: MediaMetadata,
+}
+
+/// Clips found on a card, in recording order within each layout
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct CardScan {
+    pub layouts: Vec<CardLayout>,
+    pub clips: Vec<CardClip>,
+    /// Files that could not be read, with the reason
+    pub skipped: Vec<String>,
+}
+
 /// FFmpeg availability check result
 #[derive(Debug, Serialize, Deserialize)]
 pub struct FfmpegAvailability {
@@ -124,6 +624,11 @@ pub struct BatchTranscodeRequest {
     /// Optional time segment for each input file
     #[serde(rename = "segments")]
     pub segments: Option<Vec<Option<TimeSegment>>>,
+    /// Optional segment list for each input file, used instead of its segment
+    pub segment_lists: Option<Vec<Option<Vec<TimeSegment>>>>,
+    /// Processing options applied to every file, flattened into the request
+    #[serde(flatten)]
+    pub options: TranscodeOptions,
 }
 
 /// Batch progress with file index for tracking multiple files
@@ -134,3 +639,79 @@ pub struct BatchProgress {
     pub total_files: usize,
     pub progress: TranscodeProgress,
 }
+
+/// Checksum algorithms for verified offloads and job checksums
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+pub enum HashAlgorithm {
+    XxHash64,
+    Md5,
+    Sha1,
+}
+
+/// Checksums of a job's sources and outputs, for chain-of-custody records
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct ChecksumOptions {
+    pub algorithm: HashAlgorithm,
+    /// Manifest every hash is appended to, e.g. one shared by a whole batch.
+    /// None writes a sidecar per file beside the output: "A001C003.MXF.source.md5"
+    /// and "A001C003.mov.md5". Sources are often on read-only cards.
+    pub manifest_path: Option<String>,
+}
+
+/// Hash of one file read or written by a job
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct FileChecksum {
+    pub path: String,
+    pub algorithm: HashAlgorithm,
+    pub hash: String,
+}
+
+/// Offload request from frontend: copy the sources to every destination,
+/// verify each copy by reading it back and write an MHL manifest beside it
+#[derive(Debug, Serialize, Deserialize)]
+pub struct OffloadRequest {
+    /// Folders or files to copy; folders keep their name at the destination
+    pub source_paths: Vec<String>,
+    pub destination_paths: Vec<String>,
+    /// Defaults to xxHash64
+    pub hash: Option<HashAlgorithm>,
+    /// Batch transcode started once every copy is verified. Its input paths name
+    /// files in the sources and are read from the copy in the first destination.
+    pub then_transcode: Option<BatchTranscodeRequest>,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+pub enum OffloadPhase {
+    Copy,
+    Verify,
+}
+
+/// Offload progress over every byte read and written. `progress.bitrate`
+/// carries the throughput, e.g. "212.4 MB/s".
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct OffloadProgress {
+    pub offload_id: String,
+    pub phase: OffloadPhase,
+    pub progress: TranscodeProgress,
+}
+
+/// One copied file and its verified checksum
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct OffloadedFile {
+    pub source_path: String,
+    /// The copy in each destination, in request order
+    pub destination_paths: Vec<String>,
+    pub size: u64,
+    pub hash: String,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct OffloadResult {
+    pub offload_id: String,
+    pub hash: HashAlgorithm,
+    pub files: Vec<OffloadedFile>,
+    /// Manifest written in each destination
+    pub manifest_paths: Vec<String>,
+    /// Batch started from the verified copies, if one was chained
+    pub batch_id: Option<String>,
+}
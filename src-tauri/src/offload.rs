@@ -0,0 +1,524 @@
+use crate::checksum::{Hasher, CHUNK_BYTES};
+use crate::error::TranscodeError;
+use crate::ffmpeg::transcode;
+use crate::models::{
+    HashAlgorithm, OffloadPhase, OffloadProgress, OffloadRequest, OffloadResult, OffloadedFile,
+    TranscodeProgress,
+};
+use crate::preset::format_time_as_ffmpeg;
+use std::fs::{File, OpenOptions};
+use std::io::{self, Read, Write};
+use std::path::{Path, PathBuf};
+use std::thread;
+use std::time::{Duration, Instant, SystemTime, UNIX_EPOCH};
+use tauri::Window;
+
+/// Minimum time between progress events; a card holds thousands of small files
+const PROGRESS_INTERVAL: Duration = Duration::from_millis(250);
+
+/// Buffer alignment for uncached reads, a multiple of every sector size in use
+const SECTOR_ALIGN: usize = 4096;
+
+/// A source file and where its copies go, relative to each destination
+struct SourceFile {
+    path: PathBuf,
+    relative: PathBuf,
+    size: u64,
+}
+
+/// Bytes read and written so far, reported as progress events
+struct Progress<'a> {
+    window: &'a Window,
+    offload_id: &'a str,
+    total_bytes: u64,
+    done_bytes: u64,
+    started: Instant,
+    last_event: Option<Instant>,
+}
+
+impl Progress<'_> {
+    fn advance(&mut self, phase: OffloadPhase, file: &Path, bytes: u64) {
+        self.done_bytes += bytes;
+        let now = Instant::now();
+        if self.last_event.is_some_and(|last| now - last < PROGRESS_INTERVAL)
+            && self.done_bytes < self.total_bytes
+        {
+            return;
+        }
+        self.last_event = Some(now);
+
+        let elapsed = (now - self.started).as_secs_f64();
+        let percent = if self.total_bytes > 0 {
+            self.done_bytes as f64 / self.total_bytes as f64 * 100.0
+        } else {
+            100.0
+        };
+        let rate = if elapsed > 0.0 { self.done_bytes as f64 / elapsed } else { 0.0 };
+        let remaining = self.total_bytes.saturating_sub(self.done_bytes) as f64;
+        let progress = TranscodeProgress {
+            current_file: file.to_string_lossy().to_string(),
+            progress_percent: percent.min(100.0),
+            fps: None,
+            bitrate: Some(format!("{:.1} MB/s", rate / 1_000_000.0)),
+            time_elapsed: format_time_as_ffmpeg(elapsed),
+            estimated_time: (rate > 0.0).then(|| format_time_as_ffmpeg(remaining / rate)),
+        };
+        let _ = self.window.emit(
+            "offload-progress",
+            OffloadProgress { offload_id: self.offload_id.to_string(), phase, progress },
+        );
+    }
+}
+
+/// Check the request before anything is copied, called when the job is submitted
+pub fn validate(request: &OffloadRequest) -> Result<(), TranscodeError> {
+    if request.source_paths.is_empty() {
+        return Err(TranscodeError::InvalidInput("No files provided".to_string()));
+    }
+    if request.destination_paths.is_empty() {
+        return Err(TranscodeError::InvalidInput("No destination provided".to_string()));
+    }
+
+    let mut sources = Vec::new();
+    for source in &request.source_paths {
+        let path = Path::new(source);
+        if !path.exists() {
+            return Err(TranscodeError::InvalidInput(format!("source not found: {}", source)));
+        }
+        sources.push(path.canonicalize()?);
+    }
+    for destination in &request.destination_paths {
+        let path = Path::new(destination);
+        let path = path.canonicalize().unwrap_or_else(|_| path.to_path_buf());
+        if let Some(source) = sources.iter().find(|source| path.starts_with(source)) {
+            return Err(TranscodeError::InvalidInput(format!(
+                "destination {} is inside source {}",
+                destination,
+                source.display()
+            )));
+        }
+    }
+
+    if let Some(batch) = &request.then_transcode {
+        for input_path in &batch.input_paths {
+            let path = Path::new(input_path).canonicalize().ok();
+            if !path.is_some_and(|path| sources.iter().any(|source| path.starts_with(source))) {
+                return Err(TranscodeError::InvalidInput(format!(
+                    "{} is not part of the offload",
+                    input_path
+                )));
+            }
+        }
+    }
+    Ok(())
+}
+
+/// Spawn an offload job. Progress and the outcome are sent as offload-progress,
+/// offload-complete and offload-error events.
+pub fn spawn_offload_job(offload_id: String, request: OffloadRequest, window: Window) {
+    thread::spawn(move || match execute_offload(&offload_id, request, &window) {
+        Ok(result) => {
+            let _ = window.emit("offload-complete", result);
+        }
+        Err(e) => {
+            let _ = window.emit("offload-error", (offload_id, e.to_string()));
+        }
+    });
+}
+
+/// Copy every file, verify every copy, write the manifests, then start the
+/// chained batch from the copies in the first destination
+fn execute_offload(
+    offload_id: &str,
+    request: OffloadRequest,
+    window: &Window,
+) -> Result<OffloadResult, TranscodeError> {
+    let started_at = SystemTime::now();
+    let algorithm = request.hash.unwrap_or(HashAlgorithm::XxHash64);
+    let sources = list_sources(&request.source_paths)?;
+    let destinations: Vec<PathBuf> = request.destination_paths.iter().map(PathBuf::from).collect();
+
+    // Never overwrite: an earlier offload of the same card may be all that is left
+    for file in &sources {
+        for destination in &destinations {
+            let target = destination.join(&file.relative);
+            if target.exists() {
+                return Err(TranscodeError::InvalidInput(format!(
+                    "{} already exists",
+                    target.display()
+                )));
+            }
+        }
+    }
+
+    let source_bytes: u64 = sources.iter().map(|file| file.size).sum();
+    let mut progress = Progress {
+        window,
+        offload_id,
+        total_bytes: source_bytes * (1 + destinations.len() as u64),
+        done_bytes: 0,
+        started: Instant::now(),
+        last_event: None,
+    };
+
+    // Copy: read each source once, hashing it while every destination is written
+    let mut files = Vec::new();
+    for file in &sources {
+        let targets: Vec<PathBuf> =
+            destinations.iter().map(|destination| destination.join(&file.relative)).collect();
+        let hash = copy_file(file, &targets, algorithm, &mut progress)?;
+        files.push(OffloadedFile {
+            source_path: file.path.to_string_lossy().to_string(),
+            destination_paths: targets.iter().map(|t| t.to_string_lossy().to_string()).collect(),
+            size: file.size,
+            hash,
+        });
+    }
+
+    // Verify: read every copy back from the destination drive
+    let mut verified_at = Vec::new();
+    for file in &files {
+        for target in &file.destination_paths {
+            let target = Path::new(target);
+            let hash = hash_stored(target, algorithm, &mut progress)?;
+            if hash != file.hash {
+                return Err(TranscodeError::TranscodeFailed(format!(
+                    "verification failed: {} reads back as {}, the source is {}",
+                    target.display(),
+                    hash,
+                    file.hash
+                )));
+            }
+        }
+        verified_at.push(SystemTime::now());
+    }
+
+    let finished_at = SystemTime::now();
+    let mut manifest_paths = Vec::new();
+    for destination in &destinations {
+        let manifest = Manifest {
+            algorithm,
+            sources: &sources,
+            files: &files,
+            verified_at: &verified_at,
+            started_at,
+            finished_at,
+        };
+        manifest_paths.push(manifest.write(destination)?.to_string_lossy().to_string());
+    }
+
+    let batch_id = match request.then_transcode {
+        Some(mut batch) => {
+            for input_path in batch.input_paths.iter_mut() {
+                *input_path = copied_path(input_path, &sources, &destinations[0])?;
+            }
+            Some(transcode::spawn_batch(batch, window.clone())?)
+        }
+        None => None,
+    };
+
+    Ok(OffloadResult {
+        offload_id: offload_id.to_string(),
+        hash: algorithm,
+        files,
+        manifest_paths,
+        batch_id,
+    })
+}
+
+/// Every file under the sources. A folder keeps its own name at the
+/// destination, so "/Volumes/A001" lands in "<destination>/A001". Links inside
+/// the sources are skipped: cards never need them and they can loop.
+fn list_sources(source_paths: &[String]) -> Result<Vec<SourceFile>, TranscodeError> {
+    let mut files = Vec::new();
+    for source in source_paths {
+        let source = Path::new(source).canonicalize()?;
+        let base = source.parent().unwrap_or(&source).to_path_buf();
+        let mut pending = vec![source];
+        while let Some(path) = pending.pop() {
+            let file_type = std::fs::symlink_metadata(&path)?.file_type();
+            if file_type.is_symlink() {
+                continue;
+            }
+            if file_type.is_dir() {
+                for entry in std::fs::read_dir(&path)? {
+                    pending.push(entry?.path());
+                }
+                continue;
+            }
+            let relative = path.strip_prefix(&base).unwrap_or(&path).to_path_buf();
+            if files.iter().any(|file: &SourceFile| file.relative == relative) {
+                return Err(TranscodeError::InvalidInput(format!(
+                    "two sources would both be copied to {}",
+                    relative.display()
+                )));
+            }
+            let size = std::fs::metadata(&path)?.len();
+            files.push(SourceFile { path, relative, size });
+        }
+    }
+    files.sort_by(|a, b| a.relative.cmp(&b.relative));
+    Ok(files)
+}
+
+/// Copy one file to every target, returning the hash of the bytes read
+fn copy_file(
+    file: &SourceFile,
+    targets: &[PathBuf],
+    algorithm: HashAlgorithm,
+    progress: &mut Progress,
+) -> Result<String, TranscodeError> {
+    let mut source = File::open(&file.path)?;
+    let mut outputs = Vec::new();
+    for target in targets {
+        let created = target
+            .parent()
+            .map_or(Ok(()), std::fs::create_dir_all)
+            .and_then(|_| OpenOptions::new().write(true).create_new(true).open(target))
+            .and_then(|output| skip_write_cache(&output).map(|_| output));
+        match created {
+            Ok(output) => outputs.push(output),
+            Err(e) => {
+                remove_copies(&targets[..outputs.len()]);
+                return Err(e.into());
+            }
+        }
+    }
+
+    let copied = stream_copy(&mut source, &mut outputs, &file.path, algorithm, progress);
+    if copied.is_err() {
+        // A partial copy must not pass for a finished one
+        remove_copies(targets);
+    }
+    copied
+}
+
+/// Read `source` once, hashing it and writing it to every output
+fn stream_copy(
+    source: &mut File,
+    outputs: &mut [File],
+    path: &Path,
+    algorithm: HashAlgorithm,
+    progress: &mut Progress,
+) -> Result<String, TranscodeError> {
+    let mut hasher = Hasher::new(algorithm);
+    let mut buffer = vec![0u8; CHUNK_BYTES];
+    loop {
+        let read = source.read(&mut buffer)?;
+        if read == 0 {
+            break;
+        }
+        hasher.update(&buffer[..read]);
+        for output in outputs.iter_mut() {
+            output.write_all(&buffer[..read])?;
+        }
+        progress.advance(OffloadPhase::Copy, path, read as u64);
+    }
+    // Flush to the drive so verification reads what is really stored
+    for output in outputs.iter() {
+        output.sync_all()?;
+    }
+    Ok(hasher.finish())
+}
+
+/// Hash a copy as stored on the drive, reading past the page cache that still
+/// holds the bytes just written
+fn hash_stored(
+    path: &Path,
+    algorithm: HashAlgorithm,
+    progress: &mut Progress,
+) -> Result<String, TranscodeError> {
+    let mut file = open_uncached(path)?;
+    let mut hasher = Hasher::new(algorithm);
+    // Uncached reads on Windows need a sector-aligned buffer
+    let mut buffer = vec![0u8; CHUNK_BYTES + SECTOR_ALIGN];
+    let offset = buffer.as_ptr().align_offset(SECTOR_ALIGN);
+    let buffer = &mut buffer[offset..offset + CHUNK_BYTES];
+    loop {
+        let read = file.read(buffer)?;
+        if read == 0 {
+            break;
+        }
+        hasher.update(&buffer[..read]);
+        progress.advance(OffloadPhase::Verify, path, read as u64);
+    }
+    Ok(hasher.finish())
+}
+
+/// Open a file with its cached pages dropped. They are clean after `sync_all`.
+#[cfg(any(target_os = "linux", target_os = "android"))]
+fn open_uncached(path: &Path) -> io::Result<File> {
+    use std::os::unix::io::AsRawFd;
+    let file = File::open(path)?;
+    // SAFETY: the descriptor is open for the whole call
+    let result = unsafe { libc::posix_fadvise(file.as_raw_fd(), 0, 0, libc::POSIX_FADV_DONTNEED) };
+    if result != 0 {
+        return Err(io::Error::from_raw_os_error(result));
+    }
+    Ok(file)
+}
+
+/// Open a file for reads that bypass the unified buffer cache
+#[cfg(any(target_os = "macos", target_os = "ios"))]
+fn open_uncached(path: &Path) -> io::Result<File> {
+    let file = File::open(path)?;
+    skip_write_cache(&file)?;
+    Ok(file)
+}
+
+/// Open a file for reads straight from the drive
+#[cfg(windows)]
+fn open_uncached(path: &Path) -> io::Result<File> {
+    use std::os::windows::fs::OpenOptionsExt;
+    const FILE_FLAG_NO_BUFFERING: u32 = 0x2000_0000;
+    OpenOptions::new().read(true).custom_flags(FILE_FLAG_NO_BUFFERING).open(path)
+}
+
+#[cfg(not(any(
+    target_os = "linux",
+    target_os = "android",
+    target_os = "macos",
+    target_os = "ios",
+    windows
+)))]
+fn open_uncached(_path: &Path) -> io::Result<File> {
+    Err(io::Error::new(
+        io::ErrorKind::Unsupported,
+        "copies cannot be read past the page cache on this system, so they cannot be verified",
+    ))
+}
+
+/// Keep a copy's pages out of the cache as it is written, so verification finds
+/// none there. Only macOS needs this: its uncached reads still use cached pages.
+#[cfg(any(target_os = "macos", target_os = "ios"))]
+fn skip_write_cache(file: &File) -> io::Result<()> {
+    use std::os::unix::io::AsRawFd;
+    // SAFETY: the descriptor is open for the whole call
+    if unsafe { libc::fcntl(file.as_raw_fd(), libc::F_NOCACHE, 1) } == -1 {
+        return Err(io::Error::last_os_error());
+    }
+    Ok(())
+}
+
+#[cfg(not(any(target_os = "macos", target_os = "ios")))]
+fn skip_write_cache(_file: &File) -> io::Result<()> {
+    Ok(())
+}
+
+fn remove_copies(targets: &[PathBuf]) {
+    for target in targets {
+        let _ = std::fs::remove_file(target);
+    }
+}
+
+/// Path of the copy of `input_path` in `destination`
+fn copied_path(
+    input_path: &str,
+    sources: &[SourceFile],
+    destination: &Path,
+) -> Result<String, TranscodeError> {
+    let path = Path::new(input_path).canonicalize()?;
+    sources
+        .iter()
+        .find(|file| file.path == path)
+        .map(|file| destination.join(&file.relative).to_string_lossy().to_string())
+        .ok_or_else(|| {
+            TranscodeError::InvalidInput(format!("{} is not part of the offload", input_path))
+        })
+}
+
+/// MHL (Media Hash List) manifest of one destination
+struct Manifest<'a> {
+    algorithm: HashAlgorithm,
+    sources: &'a [SourceFile],
+    files: &'a [OffloadedFile],
+    verified_at: &'a [SystemTime],
+    started_at: SystemTime,
+    finished_at: SystemTime,
+}
+
+impl Manifest<'_> {
+    /// Write the manifest at the root of `destination`, named after the first
+    /// source and the start time, e.g. "A001_2024-05-01_143205.mhl"
+    fn write(&self, destination: &Path) -> Result<PathBuf, TranscodeError> {
+        let mut xml = String::from("<?xml version=\"1.0\" encoding=\"UTF-8\"?>\n");
+        xml.push_str("<hashlist version=\"1.1\">\n");
+        xml.push_str("  <creatorinfo>\n");
+        xml.push_str(&format!(
+            "    <tool>Transcoder {}</tool>\n",
+            env!("CARGO_PKG_VERSION")
+        ));
+        xml.push_str(&format!("    <startdate>{}</startdate>\n", utc_date(self.started_at)));
+        xml.push_str(&format!("    <finishdate>{}</finishdate>\n", utc_date(self.finished_at)));
+        xml.push_str("  </creatorinfo>\n");
+
+        let element = self.algorithm.mhl_element();
+        let entries = self.sources.iter().zip(self.files).zip(self.verified_at);
+        for ((source, file), verified_at) in entries {
+            let modified = std::fs::metadata(&source.path)
+                .and_then(|metadata| metadata.modified())
+                .unwrap_or(self.started_at);
+            // MHL paths are relative to the manifest and use forward slashes
+            let relative = source.relative.to_string_lossy().replace('\\', "/");
+            xml.push_str("  <hash>\n");
+            xml.push_str(&format!("    <file>{}</file>\n", escape_xml(&relative)));
+            xml.push_str(&format!("    <size>{}</size>\n", file.size));
+            xml.push_str(&format!(
+                "    <lastmodificationdate>{}</lastmodificationdate>\n",
+                utc_date(modified)
+            ));
+            xml.push_str(&format!("    <{}>{}</{}>\n", element, file.hash, element));
+            xml.push_str(&format!("    <hashdate>{}</hashdate>\n", utc_date(*verified_at)));
+            xml.push_str("  </hash>\n");
+        }
+        xml.push_str("</hashlist>\n");
+
+        let name = self
+            .sources
+            .first()
+            .and_then(|source| source.relative.components().next())
+            .map(|component| {
+                Path::new(component.as_os_str())
+                    .file_stem()
+                    .map(|stem| stem.to_string_lossy().to_string())
+                    .unwrap_or_default()
+            })
+            .unwrap_or_else(|| "offload".to_string());
+        let stamp = utc_date(self.started_at).replace('T', "_").replace([':', 'Z'], "");
+        let path = destination.join(format!("{}_{}.mhl", name, stamp));
+        std::fs::write(&path, xml)?;
+        Ok(path)
+    }
+}
+
+/// ISO 8601 UTC date, e.g. "2024-05-01T14:32:05Z"
+fn utc_date(time: SystemTime) -> String {
+    let seconds = time.duration_since(UNIX_EPOCH).map(|d| d.as_secs()).unwrap_or(0);
+    let (days, day_seconds) = (seconds / 86_400, seconds % 86_400);
+
+    // Civil date from days since 1970-01-01 (Howard Hinnant's algorithm)
+    let z = days as i64 + 719_468;
+    let era = z.div_euclid(146_097);
+    let day_of_era = z.rem_euclid(146_097);
+    let year_of_era =
+        (day_of_era - day_of_era / 1460 + day_of_era / 36_524 - day_of_era / 146_096) / 365;
+    let day_of_year = day_of_era - (365 * year_of_era + year_of_era / 4 - year_of_era / 100);
+    let month_index = (5 * day_of_year + 2) / 153;
+    let day = day_of_year - (153 * month_index + 2) / 5 + 1;
+    let month = if month_index < 10 { month_index + 3 } else { month_index - 9 };
+    let year = year_of_era + era * 400 + (month <= 2) as i64;
+
+    format!(
+        "{:04}-{:02}-{:02}T{:02}:{:02}:{:02}Z",
+        year,
+        month,
+        day,
+        day_seconds / 3600,
+        day_seconds % 3600 / 60,
+        day_seconds % 60
+    )
+}
+
+fn escape_xml(text: &str) -> String {
+    text.replace('&', "&amp;").replace('<', "&lt;").replace('>', "&gt;")
+}
@@ -1,31 +1,76 @@
-use crate::models::{MediaMetadata, OutputPreset, TimeSegment};
+use crate::audio::{format_filter, sync_filters, synced_input_args};
+use crate::color::ColorTags;
+use crate::remux::remux_args;
+use crate::segment::{audio_join_graph, video_join_graph};
+use crate::timecode::{nearest_standard_rate, parse_framerate, Timecode};
+use crate::models::{
+    AudioBitDepth, AudioStream, CropMode, ExternalAudioMode, HdrMetadata, MediaMetadata,
+    OutputPreset, SegmentOutput, TimeSegment, ToneMapping, TranscodeRequest, VideoStream,
+};
+use std::path::Path;
 
 impl OutputPreset {
-    /// Generate ffmpeg command arguments for this preset
+    /// Generate ffmpeg command arguments for this preset.
+    /// `segment` is the effective (validated) segment, which may differ from `request.segment`.
+    /// Joined segment lists are cut from the whole input by the filter graphs instead.
     pub fn build_ffmpeg_args(
         &self,
         metadata: &MediaMetadata,
-        output: &str,
+        request: &TranscodeRequest,
         segment: Option<&TimeSegment>,
     ) -> Vec<String> {
+        if self.is_remux() {
+            return remux_args(metadata, request, segment);
+        }
+
         let mut args = Vec::new();
+        args.extend(self.source_input_args(metadata, request, segment));
 
-        // IMPORTANT: -ss must come BEFORE -i for fast seeking (keyframe seeking)
-        // This is much faster than seeking after the input
-        if let Some(seg) = segment {
-            args.push("-ss".to_string());
-            args.push(format_time_as_ffmpeg(seg.start_sec));
-        }
+        // Extra audio inputs come straight after the source inputs, so the audio
+        // graph refers to them by a fixed index
+        args.extend(self.audio_input_args(request, segment));
 
-        args.extend(vec![
-            "-i".to_string(), // Input
-            metadata.file_path.clone(),
-            "-c:v".to_string(), // Video codec
-            self.video_codec(),
-        ]);
+        match self.output_video(metadata) {
+            Some(video) => {
+                // Watermark image is the last input
+                if let Some(watermark) = &request.options.watermark {
+                    args.push("-i".to_string());
+                    args.push(watermark.path.clone());
+                }
 
-        // Add preset-specific video parameters
-        args.extend(self.preset_args());
+                args.extend(vec![
+                    "-c:v".to_string(), // Video codec
+                    self.video_codec(),
+                ]);
+
+                // Add preset-specific video parameters
+                args.extend(self.preset_args(self.output_hdr_metadata(metadata, request)));
+
+                // Video filtering
+                args.extend(self.video_filter_args(video, metadata, request, segment));
+
+                // Colour tags: match the source unless tone-mapped or explicitly converted
+                let tags = if self.tone_map_filter(metadata, request).is_some() {
+                    ColorTags::rec709()
+                } else {
+                    ColorTags::for_output(video, request.options.color_conversion.as_ref())
+                };
+                args.extend(tags.to_ffmpeg_args());
+
+                // Start timecode, written as a tmcd track
+                if let Some(timecode) = self.output_timecode(video, metadata, request, segment) {
+                    args.push("-timecode".to_string());
+                    args.push(timecode.to_string());
+                    if matches!(self, OutputPreset::H264Crf18) {
+                        // The MP4 muxer leaves the tmcd track out unless asked
+                        args.push("-write_tmcd".to_string());
+                        args.push("1".to_string());
+                    }
+                }
+            }
+            // Audio presets, or extracting the sound of a video input
+            None => args.push("-vn".to_string()),
+        }
 
         // Add end time parameter if specified
         if let Some(seg) = segment {
@@ -39,16 +84,36 @@ impl OutputPreset {
         }
 
         // Audio handling
-        args.extend(self.audio_args());
+        let loudness = self.loudness_filters(request);
+        let audio_streams = self.audio_stream_args(metadata, request, segment, &loudness);
+        if !audio_streams.is_empty() {
+            args.extend(audio_streams);
+            args.extend(self.audio_args(request));
+        }
 
-        // Format / container specific flags
+        // Output metadata tags
+        let metadata_tags = self.metadata_tags(request, segment);
+        for (key, value) in &metadata_tags {
+            args.push("-metadata".to_string());
+            args.push(format!("{}={}", key, value));
+        }
+
+        // Format / container specific flags, understood by the MOV/MP4 muxer only
+        let mut movflags = Vec::new();
         if matches!(self, OutputPreset::H264Crf18) {
+            movflags.push("+faststart");
+        }
+        if !metadata_tags.is_empty() {
+            // Custom keys are only written by the MOV/MP4 muxer with this flag
+            movflags.push("+use_metadata_tags");
+        }
+        if !movflags.is_empty() && is_mov_family(&request.output_path) {
             args.push("-movflags".to_string());
-            args.push("+faststart".to_string());
+            args.push(movflags.concat());
         }
 
         // Output file
-        args.push(output.to_string());
+        args.push(request.output_path.clone());
 
         // Overwrite without asking
         args.push("-y".to_string());
@@ -56,6 +121,593 @@ impl OutputPreset {
         args
     }
 
+    /// Video filter arguments: a plain -vf chain, or a filter graph when a
+    /// watermark input is overlaid or segments are joined.
+    /// Order: segment joins, picture processing, watermark, burn-ins.
+    fn video_filter_args(
+        &self,
+        video: &VideoStream,
+        metadata: &MediaMetadata,
+        request: &TranscodeRequest,
+        segment: Option<&TimeSegment>,
+    ) -> Vec<String> {
+        let filters = self.video_filters(video, metadata, request);
+        let burn_ins = self.burn_in_filters(video, metadata, request, segment);
+        let joined = self.joined_segments(request);
+
+        let mut graph = Vec::new();
+        let mut base = "[0:v]";
+        if let Some((segments, crossfade)) = joined {
+            graph.extend(video_join_graph(segments, crossfade, metadata.duration_sec));
+            base = "[vjoin]";
+        } else if let Some(concat_graph) = request.concat.as_ref().and_then(|c| c.video_graph()) {
+            graph.extend(concat_graph);
+            base = "[vjoin]";
+        }
+
+        let Some(watermark) = &request.options.watermark else {
+            let chain = [filters, burn_ins].concat();
+            if !graph.is_empty() {
+                let chain = if chain.is_empty() { "null".to_string() } else { chain.join(",") };
+                graph.push(format!("{}{}[vout]", base, chain));
+                return vec![
+                    "-filter_complex".to_string(),
+                    graph.join(";"),
+                    "-map".to_string(),
+                    "[vout]".to_string(),
+                ];
+            }
+            let mut args = vec!["-map".to_string(), "0:v:0".to_string()];
+            if !chain.is_empty() {
+                args.push("-vf".to_string());
+                args.push(chain.join(","));
+            }
+            return args;
+        };
+
+        if !filters.is_empty() {
+            graph.push(format!("{}{}[base]", base, filters.join(",")));
+            base = "[base]";
+        }
+        let watermark_input =
+            self.source_input_count(metadata, request) + self.audio_input_count(request);
+        graph.push(format!(
+            "[{}:v]{}[wm]",
+            watermark_input,
+            watermark.input_filter(self.output_width(video, request))
+        ));
+
+        let mut overlay = vec![watermark.overlay_filter()];
+        overlay.extend(burn_ins);
+        graph.push(format!("{}[wm]{}[vout]", base, overlay.join(",")));
+
+        vec![
+            "-filter_complex".to_string(),
+            graph.join(";"),
+            "-map".to_string(),
+            "[vout]".to_string(),
+        ]
+    }
+
+    /// Build the picture processing chain, in the order the filters must run
+    fn video_filters(
+        &self,
+        video: &VideoStream,
+        metadata: &MediaMetadata,
+        request: &TranscodeRequest,
+    ) -> Vec<String> {
+        let mut filters = Vec::new();
+
+        // Rotated (portrait) sources are turned upright first, so intra-codec outputs
+        // open upright in every NLE and crop rectangles, watermarks and burn-ins work
+        // in display space. Concat graphs turn every input on its own.
+        let rotated_by_concat = request.concat.as_ref().is_some_and(|c| c.list_path.is_none());
+        if let Some(rotate) = rotation_filter(video.rotation).filter(|_| !rotated_by_concat) {
+            filters.push(rotate.to_string());
+        }
+
+        // Retime to a constant rate before anything counts frames. The fps filter
+        // duplicates or drops frames by timestamp, so picture stays in sync with audio.
+        if let Some(rate) = self.output_frame_rate(metadata, request) {
+            filters.push(format!("fps=fps={}:round=near", rate));
+        }
+
+        // Crop early so later filters only process the kept picture.
+        // Auto crop is resolved to a fixed rectangle before the arguments are built.
+        if let Some(CropMode::Manual(rect)) = request.options.crop {
+            filters.push(format!("crop={}:{}:{}:{}", rect.width, rect.height, rect.x, rect.y));
+        }
+
+        // LUTs expect the camera encoding, so they run before any colour processing
+        if let Some(lut) = &request.options.lut {
+            filters.push(lut.filter());
+        }
+
+        // Tone mapping already lands in Rec.709, so it replaces any explicit conversion
+        if let Some(tone_map) = self.tone_map_filter(metadata, request) {
+            filters.push(tone_map);
+        } else if let Some(zscale) = request
+            .options
+            .color_conversion
+            .as_ref()
+            .and_then(|conversion| conversion.zscale_filter(video))
+        {
+            filters.push(zscale);
+        }
+
+        filters
+    }
+
+    /// Map and filter arguments for every output audio track. The sources are
+    /// mapped explicitly, otherwise ffmpeg keeps only one default audio stream.
+    /// `first_track_tail` goes on the first output track's chain: the loudnorm
+    /// correction when encoding, the loudnorm analysis when measuring. Every
+    /// track then ends with the sample rate and bit depth conversion.
+    pub fn audio_stream_args(
+        &self,
+        metadata: &MediaMetadata,
+        request: &TranscodeRequest,
+        segment: Option<&TimeSegment>,
+        first_track_tail: &[String],
+    ) -> Vec<String> {
+        let mut sources = self.audio_sources(metadata, request, segment);
+        if sources.is_empty() {
+            return Vec::new();
+        }
+        let mut args = Vec::new();
+        let mut graph = Vec::new();
+
+        let sample_rate = self.output_sample_rate(request);
+        let bit_depth = self.output_bit_depth(request);
+
+        // Concatenated inputs joined by the filter graph: join each selected track first
+        if let Some(concat) = &request.concat {
+            if !self.replaces_source_audio(request) {
+                for track in metadata.selected_audio(request.options.audio_tracks.as_deref()) {
+                    graph.extend(concat.audio_graph(track.index).into_iter().flatten());
+                }
+            }
+        }
+
+        // Joined segments: cut every source into the segments and join them, so
+        // the rest of the chain sees one continuous source
+        let joined = self.joined_segments(request);
+        if let Some((segments, crossfade)) = joined {
+            for (index, (input, _, filters)) in sources.iter_mut().enumerate() {
+                let label = format!("ajoin{}", index);
+                graph.extend(audio_join_graph(
+                    input,
+                    filters,
+                    segments,
+                    crossfade,
+                    metadata.duration_sec,
+                    &label,
+                ));
+                *input = label;
+                filters.clear();
+            }
+        }
+
+        // Without remapping each source is its own output track
+        let Some(layout) = &request.options.audio_layout else {
+            for (output_index, (input, stream, mut filters)) in sources.into_iter().enumerate() {
+                let tail = if output_index == 0 { first_track_tail } else { &[] };
+                filters.extend_from_slice(tail);
+                filters.extend(format_filter(stream, sample_rate, bit_depth, !tail.is_empty()));
+
+                args.push("-map".to_string());
+                if !graph.is_empty() {
+                    // Filter graph outputs take no per-stream filters
+                    let chain = if filters.is_empty() {
+                        "anull".to_string()
+                    } else {
+                        filters.join(",")
+                    };
+                    graph.push(format!("[{}]{}[aout{}]", input, chain, output_index));
+                    args.push(format!("[aout{}]", output_index));
+                } else {
+                    args.push(input);
+                    if !filters.is_empty() {
+                        args.push(format!("-filter:a:{}", output_index));
+                        args.push(filters.join(","));
+                    }
+                }
+            }
+            if graph.is_empty() {
+                return args;
+            }
+            return [vec!["-filter_complex".to_string(), graph.join(";")], args].concat();
+        };
+
+        // Remapping: merge the sources into one signal, then pan it into the outputs.
+        // amerge brings every source to the first one's rate.
+        let channels: u32 = sources.iter().map(|(_, stream, _)| stream.channels as u32).sum();
+        let first_stream = sources[0].1;
+        let source_count = sources.len();
+        let mut labels = Vec::new();
+        for (index, (input, _, filters)) in sources.into_iter().enumerate() {
+            if filters.is_empty() {
+                labels.push(format!("[{}]", input));
+            } else {
+                graph.push(format!("[{}]{}[asrc{}]", input, filters.join(","), index));
+                labels.push(format!("[asrc{}]", index));
+            }
+        }
+        let mix = if source_count > 1 {
+            graph.push(format!("{}amerge=inputs={}[amix]", labels.concat(), source_count));
+            "[amix]".to_string()
+        } else {
+            labels.concat()
+        };
+
+        let outputs = layout.output_filters(channels);
+        let split: Vec<String> = if outputs.len() > 1 {
+            let labels: Vec<String> =
+                (0..outputs.len()).map(|i| format!("[asplit{}]", i)).collect();
+            graph.push(format!("{}asplit={}{}", mix, outputs.len(), labels.concat()));
+            labels
+        } else {
+            vec![mix]
+        };
+
+        for (output_index, (source, pan)) in split.iter().zip(outputs).enumerate() {
+            let mut filters: Vec<String> = pan.into_iter().collect();
+            let tail = if output_index == 0 { first_track_tail } else { &[] };
+            filters.extend_from_slice(tail);
+            filters.extend(format_filter(first_stream, sample_rate, bit_depth, !tail.is_empty()));
+            if filters.is_empty() {
+                filters.push("anull".to_string());
+            }
+            graph.push(format!("{}{}[aout{}]", source, filters.join(","), output_index));
+            args.push("-map".to_string());
+            args.push(format!("[aout{}]", output_index));
+        }
+
+        [vec!["-filter_complex".to_string(), graph.join(";")], args].concat()
+    }
+
+    /// Audio streams feeding the output tracks, in order: the selected source
+    /// tracks unless replaced, then any external recording
+    pub fn audio_source_streams<'a>(
+        &self,
+        metadata: &'a MediaMetadata,
+        request: &'a TranscodeRequest,
+    ) -> Vec<&'a AudioStream> {
+        self.audio_sources(metadata, request, None)
+            .into_iter()
+            .map(|(_, stream, _)| stream)
+            .collect()
+    }
+
+    /// Audio sources with their stream specifier and the filters run on each
+    fn audio_sources<'a>(
+        &self,
+        metadata: &'a MediaMetadata,
+        request: &'a TranscodeRequest,
+        segment: Option<&TimeSegment>,
+    ) -> Vec<(String, &'a AudioStream, Vec<String>)> {
+        let output_start = segment.map(|seg| seg.start_sec).unwrap_or(0.0);
+        let duration = segment
+            .map(|seg| seg.duration(metadata.duration_sec))
+            .unwrap_or(metadata.duration_sec);
+        let shift = self.source_audio_shift(request);
+        let mut sources = Vec::new();
+
+        if !self.replaces_source_audio(request) {
+            let pre_filters = self.audio_filters_before_loudness(metadata, request);
+            for track in metadata.selected_audio(request.options.audio_tracks.as_deref()) {
+                let (input, filters) = match shift {
+                    // Shifted audio is read from its own input of the source, after the source
+                    Some(offset) => (
+                        format!("{}:a:{}", self.source_input_count(metadata, request), track.index),
+                        [pre_filters.clone(), sync_filters(offset, output_start, duration)].concat(),
+                    ),
+                    None => {
+                        let input = match &request.concat {
+                            Some(concat) => concat.audio_label(track.index),
+                            None => metadata.audio_label(track.index),
+                        };
+                        (input, pre_filters.clone())
+                    }
+                };
+                sources.push((input, track, filters));
+            }
+        }
+
+        // The recording follows the shifted source audio input, if any
+        if let Some((external, stream)) = request
+            .options
+            .external_audio
+            .as_ref()
+            .and_then(|external| Some((external, external.stream.as_ref()?)))
+        {
+            let input = self.source_input_count(metadata, request) + shift.is_some() as usize;
+            sources.push((
+                format!("{}:a:0", input),
+                stream,
+                sync_filters(external.offset_seconds(), output_start, duration),
+            ));
+        }
+
+        sources
+    }
+
+    /// Segments cut from the whole input and joined into the output, with the crossfade
+    fn joined_segments<'a>(
+        &self,
+        request: &'a TranscodeRequest,
+    ) -> Option<(&'a [TimeSegment], Option<f64>)> {
+        match request.options.segment_output {
+            Some(output @ SegmentOutput::Joined { .. }) => {
+                Some((request.segment_list.as_deref()?, output.crossfade()))
+            }
+            _ => None,
+        }
+    }
+
+    /// Where the output starts in the source, in seconds: the segment start, or
+    /// the first joined segment's
+    fn output_start(&self, request: &TranscodeRequest, segment: Option<&TimeSegment>) -> f64 {
+        segment
+            .or_else(|| self.joined_segments(request)?.0.first())
+            .map(|seg| seg.start_sec)
+            .unwrap_or(0.0)
+    }
+
+    /// Source inputs: every file of a concat job, otherwise the source seeked
+    /// to the segment start
+    pub fn source_input_args(
+        &self,
+        metadata: &MediaMetadata,
+        request: &TranscodeRequest,
+        segment: Option<&TimeSegment>,
+    ) -> Vec<String> {
+        if let Some(concat) = &request.concat {
+            return concat.input_args(!self.is_remux());
+        }
+
+        let mut args = Vec::new();
+        // IMPORTANT: -ss must come BEFORE -i for fast seeking (keyframe seeking)
+        // This is much faster than seeking after the input
+        if let Some(seg) = segment {
+            args.push("-ss".to_string());
+            args.push(format_time_as_ffmpeg(seg.start_sec));
+        }
+        let rotation = metadata.video.as_ref().map_or(0, |video| video.rotation);
+        args.extend(upright_input_args(rotation, !self.is_remux()));
+        args.push("-i".to_string());
+        args.push(metadata.file_path.clone());
+        // Audio recorded beside the picture, seeked the same way
+        for audio_file in &metadata.audio_files {
+            if let Some(seg) = segment {
+                args.push("-ss".to_string());
+                args.push(format_time_as_ffmpeg(seg.start_sec));
+            }
+            args.push("-i".to_string());
+            args.push(audio_file.clone());
+        }
+        args
+    }
+
+    /// Number of inputs added by `source_input_args`
+    fn source_input_count(&self, metadata: &MediaMetadata, request: &TranscodeRequest) -> usize {
+        request
+            .concat
+            .as_ref()
+            .map_or(1 + metadata.audio_files.len(), |concat| concat.input_count())
+    }
+
+    /// Inputs after the source that feed audio: the source again when its audio
+    /// is shifted, then the external recording
+    pub fn audio_input_args(
+        &self,
+        request: &TranscodeRequest,
+        segment: Option<&TimeSegment>,
+    ) -> Vec<String> {
+        let output_start = segment.map(|seg| seg.start_sec).unwrap_or(0.0);
+        let mut args = Vec::new();
+        if let Some(offset) = self.source_audio_shift(request) {
+            args.extend(synced_input_args(&request.input_path, offset, output_start));
+        }
+        if let Some(external) = &request.options.external_audio {
+            args.extend(synced_input_args(&external.path, external.offset_seconds(), output_start));
+        }
+        args
+    }
+
+    /// Number of inputs added by `audio_input_args`
+    fn audio_input_count(&self, request: &TranscodeRequest) -> usize {
+        self.source_audio_shift(request).is_some() as usize
+            + request.options.external_audio.is_some() as usize
+    }
+
+    /// Sync correction of the source audio in seconds, None when it is not shifted
+    fn source_audio_shift(&self, request: &TranscodeRequest) -> Option<f64> {
+        request
+            .options
+            .audio_offset_ms
+            .filter(|ms| *ms != 0 && !self.replaces_source_audio(request))
+            .map(|ms| ms as f64 / 1000.0)
+    }
+
+    /// Whether external audio replaces the source audio
+    fn replaces_source_audio(&self, request: &TranscodeRequest) -> bool {
+        request
+            .options
+            .external_audio
+            .as_ref()
+            .is_some_and(|external| external.mode == ExternalAudioMode::Replace)
+    }
+
+    /// Second-pass loudnorm for the first output track, once the analysis pass has run
+    fn loudness_filters(&self, request: &TranscodeRequest) -> Vec<String> {
+        request
+            .options
+            .loudness
+            .as_ref()
+            .and_then(|loudness| loudness.apply_filter())
+            .into_iter()
+            .collect()
+    }
+
+    /// Audio filters that run before loudness normalisation and any remapping.
+    /// The analysis pass runs these too, so it measures exactly what the second pass corrects.
+    fn audio_filters_before_loudness(
+        &self,
+        metadata: &MediaMetadata,
+        request: &TranscodeRequest,
+    ) -> Vec<String> {
+        let mut filters = Vec::new();
+
+        // When video is retimed, pad or trim audio to its timestamps so drift
+        // from a VFR source cannot accumulate
+        if self.output_frame_rate(metadata, request).is_some() {
+            filters.push("aresample=async=1:first_pts=0".to_string());
+        }
+
+        filters
+    }
+
+    /// Constant output frame rate: the requested rate, or the nearest standard
+    /// rate for VFR sources. None keeps the source timing or has no picture.
+    fn output_frame_rate(&self, metadata: &MediaMetadata, request: &TranscodeRequest) -> Option<String> {
+        let video = self.output_video(metadata)?;
+        match &request.options.frame_rate {
+            Some(rate) => Some(rate.clone()),
+            None if video.is_vfr => {
+                let avg_fps = parse_framerate(&video.avg_framerate).unwrap_or(25.0);
+                Some(nearest_standard_rate(avg_fps).to_string())
+            }
+            None => None,
+        }
+    }
+
+    /// Displayed picture width after cropping
+    fn output_width(&self, video: &VideoStream, request: &TranscodeRequest) -> u32 {
+        match request.options.crop {
+            Some(CropMode::Manual(rect)) => rect.width,
+            _ => video.display_width,
+        }
+    }
+
+    /// Burn-in drawtext filters - these go last so the text is not colour processed
+    fn burn_in_filters(
+        &self,
+        video: &VideoStream,
+        metadata: &MediaMetadata,
+        request: &TranscodeRequest,
+        segment: Option<&TimeSegment>,
+    ) -> Vec<String> {
+        match &request.options.burn_in {
+            Some(burn_in) => {
+                let segment_start = self.output_start(request, segment);
+                let framerate = self.timecode_rate(video, metadata, request);
+                burn_in.filters(metadata, &framerate, segment_start)
+            }
+            None => Vec::new(),
+        }
+    }
+
+    /// Frame rate the output timecode counts at: the output rate, which is the
+    /// source rate unless the clip is retimed
+    fn timecode_rate(
+        &self,
+        video: &VideoStream,
+        metadata: &MediaMetadata,
+        request: &TranscodeRequest,
+    ) -> String {
+        self.output_frame_rate(metadata, request)
+            .unwrap_or_else(|| video.framerate.clone())
+    }
+
+    /// Source start timecode advanced to the segment start, so proxies and
+    /// segments still relink and conform to their source
+    fn output_timecode(
+        &self,
+        video: &VideoStream,
+        metadata: &MediaMetadata,
+        request: &TranscodeRequest,
+        segment: Option<&TimeSegment>,
+    ) -> Option<Timecode> {
+        let timecode = Timecode::parse(metadata.timecode.as_deref()?)?;
+        let segment_start = self.output_start(request, segment);
+        let framerate = self.timecode_rate(video, metadata, request);
+        Some(timecode.offset_by_seconds(segment_start, &framerate))
+    }
+
+    /// Custom metadata tags describing how the output was made
+    fn metadata_tags(
+        &self,
+        request: &TranscodeRequest,
+        segment: Option<&TimeSegment>,
+    ) -> Vec<(String, String)> {
+        let mut tags = Vec::new();
+
+        if let Some(lut) = request.options.lut.as_ref().filter(|_| !self.is_audio_only()) {
+            tags.push(("lut_file".to_string(), lut.file_name()));
+        }
+
+        // Handles applied to the segment, or to each segment of a joined output
+        match segment {
+            Some(seg) => tags.extend(seg.handle_tags(None)),
+            None => {
+                for (index, seg) in request.segment_list.iter().flatten().enumerate() {
+                    tags.extend(seg.handle_tags(Some(index + 1)));
+                }
+            }
+        }
+
+        tags
+    }
+
+    /// HDR tone-mapping chain for this job, None when the output stays HDR or the source is SDR.
+    /// Proxy and review presets tone-map automatically unless the request says otherwise.
+    fn tone_map_filter(&self, metadata: &MediaMetadata, request: &TranscodeRequest) -> Option<String> {
+        let mode = match request.options.tone_mapping {
+            Some(mode) => mode,
+            None if self.is_review() => ToneMapping::Hable,
+            None => ToneMapping::Off,
+        };
+        mode.filter_chain(self.output_video(metadata)?, self.pix_fmt())
+    }
+
+    /// The source picture when this preset encodes video, None for audio presets
+    fn output_video<'a>(&self, metadata: &'a MediaMetadata) -> Option<&'a VideoStream> {
+        if self.is_audio_only() {
+            None
+        } else {
+            metadata.video.as_ref()
+        }
+    }
+
+    /// Presets that write audio alone, dropping any picture
+    pub fn is_audio_only(&self) -> bool {
+        matches!(self, OutputPreset::WavPcm24 | OutputPreset::Flac | OutputPreset::Aac)
+    }
+
+    /// Whether this preset copies the streams instead of encoding them
+    pub fn is_remux(&self) -> bool {
+        matches!(self, OutputPreset::Remux)
+    }
+
+    /// Whether the output container can hold several audio tracks
+    pub fn is_multi_track(&self) -> bool {
+        !matches!(self, OutputPreset::WavPcm24 | OutputPreset::Flac)
+    }
+
+    /// Proxy and review presets, as opposed to mastering presets
+    fn is_review(&self) -> bool {
+        matches!(self, OutputPreset::ProRes422Proxy | OutputPreset::H264Crf18)
+    }
+
+    /// Pixel format the preset encodes to
+    fn pix_fmt(&self) -> &'static str {
+        match self {
+            OutputPreset::ProRes422Proxy | OutputPreset::H264Crf18 => "yuv420p",
+            _ => "yuv422p10le",
+        }
+    }
+
     fn video_codec(&self) -> String {
         match self {
             OutputPreset::ProRes422 => "prores_ks".to_string(),
@@ -63,10 +715,48 @@ impl OutputPreset {
             OutputPreset::ProRes422Proxy => "prores_ks".to_string(),
             OutputPreset::DnxHRHQX => "dnxhd".to_string(),
             OutputPreset::H264Crf18 => "libx264".to_string(),
+            OutputPreset::Remux => "copy".to_string(),
+            // Audio presets never encode video
+            OutputPreset::WavPcm24 | OutputPreset::Flac | OutputPreset::Aac => String::new(),
+        }
+    }
+
+    /// Mastering display and light level metadata to write to the output: the
+    /// source's, when the HDR signal passes through untouched
+    fn output_hdr_metadata<'a>(
+        &self,
+        metadata: &'a MediaMetadata,
+        request: &TranscodeRequest,
+    ) -> Option<&'a HdrMetadata> {
+        let video = self.output_video(metadata)?;
+        video.hdr_format?;
+        if self.tone_map_filter(metadata, request).is_some()
+            || request.options.color_conversion.as_ref().is_some_and(|c| c.standard.is_some())
+        {
+            return None;
+        }
+        video.hdr_metadata.as_ref()
+    }
+
+    /// Warning for an HDR master whose mastering metadata the encoder cannot
+    /// carry; only x264 takes it, stream copies keep it as it is
+    pub fn hdr_metadata_warning(
+        &self,
+        metadata: &MediaMetadata,
+        request: &TranscodeRequest,
+    ) -> Option<String> {
+        if self.is_remux() || matches!(self, OutputPreset::H264Crf18) {
+            return None;
         }
+        self.output_hdr_metadata(metadata, request)?;
+        Some(format!(
+            "HDR mastering display and MaxCLL metadata not preserved: {} cannot carry it",
+            self.video_codec()
+        ))
     }
 
-    fn preset_args(&self) -> Vec<String> {
+    /// `hdr` is written to the stream by encoders that support it
+    fn preset_args(&self, hdr: Option<&HdrMetadata>) -> Vec<String> {
         match self {
             OutputPreset::ProRes422 => vec![
                 "-profile:v".to_string(),
@@ -109,29 +799,123 @@ impl OutputPreset {
                 "4.1".to_string(),
                 // x264 fine-tuning
                 "-x264-params".to_string(),
-                "keyint=240:min-keyint=24:scenecut=40:\
-bframes=3:b-pyramid=normal:ref=4:\
-deblock=-1,-1:aq-mode=3:aq-strength=0.8:\
-psy-rd=1.0:psy-trellis=0.15:qcomp=0.65:\
-rc-lookahead=40:weightp=2"
-                    .to_string(),
+                x264_params(hdr),
             ],
+            OutputPreset::WavPcm24
+            | OutputPreset::Flac
+            | OutputPreset::Aac
+            | OutputPreset::Remux => Vec::new(),
         }
     }
 
-    fn audio_args(&self) -> Vec<String> {
+    fn audio_args(&self, request: &TranscodeRequest) -> Vec<String> {
         match self {
             // Proxy preset uses AAC for reduced file size
-            OutputPreset::ProRes422Proxy | OutputPreset::H264Crf18 => vec![
+            _ if self.encodes_aac() => vec![
                 "-c:a".to_string(),
                 "aac".to_string(),
                 "-b:a".to_string(),
                 "320k".to_string(),
             ],
-            // Other presets use PCM 16-bit for Adobe compatibility
-            _ => vec!["-c:a".to_string(), "pcm_s16le".to_string()],
+            // Lossless, the bit depth follows the sample format
+            OutputPreset::Flac => vec!["-c:a".to_string(), "flac".to_string()],
+            // PCM, 16-bit by default for Adobe compatibility
+            _ => vec![
+                "-c:a".to_string(),
+                self.output_bit_depth(request)
+                    .unwrap_or(AudioBitDepth::Int16)
+                    .pcm_codec()
+                    .to_string(),
+            ],
+        }
+    }
+
+    /// Presets encoding AAC rather than PCM or FLAC
+    pub fn encodes_aac(&self) -> bool {
+        matches!(
+            self,
+            OutputPreset::ProRes422Proxy | OutputPreset::H264Crf18 | OutputPreset::Aac
+        )
+    }
+
+    /// Output sample rate: the requested rate, else 48 kHz for everything but
+    /// FLAC, which keeps the source rate
+    fn output_sample_rate(&self, request: &TranscodeRequest) -> Option<u32> {
+        request
+            .options
+            .audio_format
+            .and_then(|format| format.sample_rate)
+            .or(match self {
+                OutputPreset::Flac => None,
+                _ => Some(48000),
+            })
+    }
+
+    /// Output bit depth: the requested depth, else the preset's PCM depth.
+    /// None keeps the source depth (FLAC) or does not apply (AAC).
+    fn output_bit_depth(&self, request: &TranscodeRequest) -> Option<AudioBitDepth> {
+        request
+            .options
+            .audio_format
+            .and_then(|format| format.bit_depth)
+            .or(match self {
+                OutputPreset::ProRes422 | OutputPreset::ProRes422LT | OutputPreset::DnxHRHQX => {
+                    Some(AudioBitDepth::Int16)
+                }
+                OutputPreset::WavPcm24 => Some(AudioBitDepth::Int24),
+                _ => None,
+            })
+    }
+}
+
+/// Filter turning a picture with this clockwise display rotation upright
+pub fn rotation_filter(rotation: u32) -> Option<&'static str> {
+    match rotation {
+        90 => Some("transpose=clock"),
+        180 => Some("hflip,vflip"),
+        270 => Some("transpose=cclock"),
+        _ => None,
+    }
+}
+
+/// Input options for a source whose picture the filters turn upright from its
+/// probed rotation. When `clear_matrix`, the display matrix is also reset, as
+/// ffmpeg otherwise copies it to the output and players rotate the picture twice.
+pub fn upright_input_args(rotation: u32, clear_matrix: bool) -> Vec<String> {
+    let mut args = vec!["-noautorotate".to_string()];
+    if clear_matrix && rotation != 0 {
+        args.push("-display_rotation:v:0".to_string());
+        args.push("0".to_string());
+    }
+    args
+}
+
+/// Whether the output is written by the MOV/MP4 muxer, judged by its extension
+pub fn is_mov_family(output_path: &str) -> bool {
+    Path::new(output_path)
+        .extension()
+        .map(|ext| ext.to_string_lossy().to_lowercase())
+        .is_some_and(|ext| matches!(ext.as_str(), "mov" | "mp4" | "m4v" | "m4a"))
+}
+
+/// x264 tuning, with the source's mastering display and content light levels
+/// when an HDR master is encoded without touching the signal
+fn x264_params(hdr: Option<&HdrMetadata>) -> String {
+    let mut params = "keyint=240:min-keyint=24:scenecut=40:\
+bframes=3:b-pyramid=normal:ref=4:\
+deblock=-1,-1:aq-mode=3:aq-strength=0.8:\
+psy-rd=1.0:psy-trellis=0.15:qcomp=0.65:\
+rc-lookahead=40:weightp=2"
+        .to_string();
+    if let Some(hdr) = hdr {
+        if let Some(display) = &hdr.mastering_display {
+            params.push_str(&format!(":mastering-display={}", display));
+        }
+        if let (Some(max_cll), max_fall) = (hdr.max_cll, hdr.max_fall) {
+            params.push_str(&format!(":cll={},{}", max_cll, max_fall.unwrap_or(0)));
         }
     }
+    params
 }
 
 /// Convert seconds to HH:MM:SS.mmm format for ffmpeg
@@ -142,3 +926,94 @@ pub fn format_time_as_ffmpeg(seconds: f64) -> String {
     let secs = seconds % 60.0;
     format!("{:02}:{:02}:{:06.3}", hours, minutes, secs)
 }
+
+/// Quote a value (e.g. a file path) for use as a filter option in a filtergraph.
+/// ffmpeg unescapes filtergraphs twice - once for the graph, once for the option
+/// list - so the value is escaped for the option parser, then quoted for the graph.
+pub fn quote_filter_value(value: &str) -> String {
+    let mut escaped = String::with_capacity(value.len());
+    for c in value.chars() {
+        if matches!(c, '\\' | ':' | '\'') {
+            escaped.push('\\');
+        }
+        escaped.push(c);
+    }
+    format!("'{}'", escaped.replace('\'', "'\\''"))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn quote_filter_value_escapes_for_both_parsers() {
+        assert_eq!(quote_filter_value("/luts/look.cube"), "'/luts/look.cube'");
+        assert_eq!(quote_filter_value("C:\\LUTs\\look.cube"), "'C\\:\\\\LUTs\\\\look.cube'");
+        assert_eq!(quote_filter_value("it's.cube"), "'it\\'\\''s.cube'");
+    }
+
+    fn rotated_source(rotation: u32) -> MediaMetadata {
+        MediaMetadata {
+            file_path: "/card/IMG_0001.MOV".to_string(),
+            duration_sec: 10.0,
+            video: Some(VideoStream {
+                codec: "hevc".to_string(),
+                width: 1920,
+                height: 1080,
+                rotation,
+                display_width: 1080,
+                display_height: 1920,
+                framerate: "30/1".to_string(),
+                avg_framerate: "30/1".to_string(),
+                is_vfr: false,
+                bit_depth: 8,
+                pix_fmt: "yuv420p".to_string(),
+                chroma_subsampling: "4:2:0".to_string(),
+                color_primaries: None,
+                color_transfer: None,
+                color_space: None,
+                color_range: None,
+                hdr_format: None,
+                hdr_metadata: None,
+            }),
+            audio_streams: Vec::new(),
+            timecode: None,
+            audio_files: Vec::new(),
+        }
+    }
+
+    fn request(preset: OutputPreset) -> TranscodeRequest {
+        TranscodeRequest {
+            input_path: "/card/IMG_0001.MOV".to_string(),
+            output_path: "/out/IMG_0001.mov".to_string(),
+            preset,
+            segment: None,
+            segment_list: None,
+            options: Default::default(),
+            concat: None,
+        }
+    }
+
+    #[test]
+    fn rotated_sources_are_encoded_upright_without_a_display_matrix() {
+        let metadata = rotated_source(90);
+        let encode = request(OutputPreset::ProRes422);
+        let args = encode.preset.build_ffmpeg_args(&metadata, &encode, None);
+        let input = args.iter().position(|arg| arg == "-i").unwrap();
+        assert_eq!(
+            args[..input],
+            ["-noautorotate", "-display_rotation:v:0", "0"].map(String::from)
+        );
+        let filters = args.iter().position(|arg| arg == "-vf").unwrap();
+        assert!(args[filters + 1].starts_with("transpose=clock"));
+
+        // An upright source needs no override
+        let args = encode.preset.build_ffmpeg_args(&rotated_source(0), &encode, None);
+        assert!(!args.iter().any(|arg| arg == "-display_rotation:v:0"));
+
+        // A stream copy keeps the matrix, so players still turn the picture
+        let copy = request(OutputPreset::Remux);
+        let args = copy.preset.build_ffmpeg_args(&metadata, &copy, None);
+        assert!(!args.iter().any(|arg| arg == "-display_rotation:v:0"));
+    }
+}
@@ -0,0 +1,237 @@
+use crate::error::TranscodeError;
+use crate::models::{MediaMetadata, SegmentOutput, TimeSegment, TranscodeOptions, TranscodeRequest};
+use crate::preset::{format_time_as_ffmpeg, is_mov_family};
+use crate::timecode::Timecode;
+use std::path::Path;
+
+/// Containers a remux can write, chosen by the output file extension
+#[derive(Clone, Copy, PartialEq)]
+enum Container {
+    Mov,
+    Mp4,
+    Mkv,
+}
+
+impl Container {
+    fn from_path(output_path: &str) -> Result<Self, TranscodeError> {
+        let extension = Path::new(output_path)
+            .extension()
+            .map(|ext| ext.to_string_lossy().to_lowercase())
+            .unwrap_or_default();
+        match extension.as_str() {
+            "mov" => Ok(Container::Mov),
+            "mp4" | "m4v" => Ok(Container::Mp4),
+            "mkv" => Ok(Container::Mkv),
+            _ => Err(TranscodeError::InvalidOption(format!(
+                "remux writes .mov, .mp4 or .mkv, not {}",
+                output_path
+            ))),
+        }
+    }
+
+    fn name(self) -> &'static str {
+        match self {
+            Container::Mov => "MOV",
+            Container::Mp4 => "MP4",
+            Container::Mkv => "MKV",
+        }
+    }
+
+    /// Whether the container can carry a video stream of this ffprobe codec name
+    fn accepts_video(self, codec: &str) -> bool {
+        match self {
+            Container::Mov => matches!(
+                codec,
+                "h264" | "hevc" | "prores" | "dnxhd" | "mjpeg" | "mpeg2video" | "mpeg4"
+            ),
+            Container::Mp4 => {
+                matches!(codec, "h264" | "hevc" | "av1" | "vp9" | "mpeg4" | "mpeg2video")
+            }
+            Container::Mkv => true,
+        }
+    }
+
+    /// Whether the container can carry an audio stream of this ffprobe codec name
+    fn accepts_audio(self, codec: &str) -> bool {
+        match self {
+            Container::Mov => {
+                matches!(codec, "aac" | "alac" | "mp3" | "ac3" | "eac3")
+                    || codec.starts_with("pcm_")
+            }
+            Container::Mp4 => {
+                matches!(codec, "aac" | "alac" | "mp3" | "ac3" | "eac3" | "opus" | "flac")
+            }
+            Container::Mkv => true,
+        }
+    }
+}
+
+/// Reject options that need decoded pictures or sound, called when the job is submitted
+pub fn validate_options(options: &TranscodeOptions) -> Result<(), TranscodeError> {
+    let needs_encode = [
+        ("frame rate conversion", options.frame_rate.is_some()),
+        ("crop", options.crop.is_some()),
+        ("colour conversion", options.color_conversion.is_some()),
+        ("tone mapping", options.tone_mapping.is_some()),
+        ("LUT", options.lut.is_some()),
+        ("burn-in", options.burn_in.is_some()),
+        ("watermark", options.watermark.is_some()),
+        ("loudness normalisation", options.loudness.is_some()),
+        ("audio layout", options.audio_layout.is_some()),
+        ("external audio", options.external_audio.is_some()),
+        ("audio offset", options.audio_offset_ms.is_some_and(|ms| ms != 0)),
+        ("audio format", options.audio_format.is_some()),
+        (
+            "joining segments",
+            matches!(options.segment_output, Some(SegmentOutput::Joined { .. })),
+        ),
+    ];
+    match needs_encode.iter().find(|(_, used)| *used) {
+        Some((name, _)) => Err(TranscodeError::InvalidOption(format!(
+            "remux copies the streams unchanged, {} needs an encoding preset",
+            name
+        ))),
+        None => Ok(()),
+    }
+}
+
+/// Check every copied stream fits the output container, called when the job starts
+pub fn check_streams(
+    metadata: &MediaMetadata,
+    request: &TranscodeRequest,
+) -> Result<(), TranscodeError> {
+    let container = Container::from_path(&request.output_path)?;
+    if let Some(video) = &metadata.video {
+        if !container.accepts_video(&video.codec) {
+            return Err(TranscodeError::InvalidOption(format!(
+                "{} video cannot be copied into {}, choose .mkv or an encoding preset",
+                video.codec,
+                container.name()
+            )));
+        }
+    }
+    for track in metadata.selected_audio(request.options.audio_tracks.as_deref()) {
+        if !container.accepts_audio(&track.codec) {
+            return Err(TranscodeError::InvalidOption(format!(
+                "{} audio (track {}) cannot be copied into {}, choose .mkv or leave the track out",
+                track.codec,
+                track.index,
+                container.name()
+            )));
+        }
+    }
+    Ok(())
+}
+
+/// ffmpeg arguments copying the picture and the selected audio tracks into the
+/// new container. `segment` starts on a keyframe, so the cut is where it says.
+pub fn remux_args(
+    metadata: &MediaMetadata,
+    request: &TranscodeRequest,
+    segment: Option<&TimeSegment>,
+) -> Vec<String> {
+    let mut args = Vec::new();
+
+    args.extend(request.preset.source_input_args(metadata, request, segment));
+
+    if let Some(seg) = segment {
+        if let Some(end) = seg.end_sec {
+            args.push("-t".to_string());
+            args.push(format_time_as_ffmpeg((end - seg.start_sec).max(0.0)));
+        }
+    }
+
+    // Only the picture and audio are copied; subtitle and data tracks rarely
+    // survive a change of container
+    if let Some(video) = &metadata.video {
+        args.push("-map".to_string());
+        args.push("0:v:0".to_string());
+        // Apple players only open HEVC in MOV and MP4 under the hvc1 tag
+        let container = Container::from_path(&request.output_path).ok();
+        if video.codec == "hevc" && container != Some(Container::Mkv) {
+            args.push("-tag:v".to_string());
+            args.push("hvc1".to_string());
+        }
+    }
+    for track in metadata.selected_audio(request.options.audio_tracks.as_deref()) {
+        args.push("-map".to_string());
+        args.push(match &request.concat {
+            Some(concat) => concat.audio_label(track.index),
+            None => metadata.audio_label(track.index),
+        });
+    }
+    args.extend(vec!["-c".to_string(), "copy".to_string()]);
+
+    // Start timecode, advanced to the keyframe the copy starts on
+    if let (Some(video), Some(timecode)) = (
+        &metadata.video,
+        metadata.timecode.as_deref().and_then(Timecode::parse),
+    ) {
+        let start = segment.map(|seg| seg.start_sec).unwrap_or(0.0);
+        args.push("-timecode".to_string());
+        args.push(timecode.offset_by_seconds(start, &video.framerate).to_string());
+    }
+
+    // Handles applied to the segment, as the encoding presets write them
+    let handle_tags = segment.map(|seg| seg.handle_tags(None)).unwrap_or_default();
+    for (key, value) in &handle_tags {
+        args.push("-metadata".to_string());
+        args.push(format!("{}={}", key, value));
+    }
+    if !handle_tags.is_empty() && is_mov_family(&request.output_path) {
+        args.push("-movflags".to_string());
+        args.push("+use_metadata_tags".to_string());
+    }
+
+    args.extend(vec![
+        "-map_metadata".to_string(),
+        "0".to_string(),
+        // Camera streams (MTS) often start at a large or negative timestamp
+        "-avoid_negative_ts".to_string(),
+        "make_zero".to_string(),
+        request.output_path.clone(),
+        "-y".to_string(),
+    ]);
+    args
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::models::{CropMode, CropRect, ToneMapping};
+
+    fn rejected(options: TranscodeOptions) -> String {
+        validate_options(&options).unwrap_err().to_string()
+    }
+
+    #[test]
+    fn accepts_options_that_keep_the_streams() {
+        assert!(validate_options(&TranscodeOptions::default()).is_ok());
+        let options = TranscodeOptions {
+            audio_tracks: Some(vec![0, 2]),
+            audio_offset_ms: Some(0),
+            segment_output: Some(SegmentOutput::Numbered),
+            ..Default::default()
+        };
+        assert!(validate_options(&options).is_ok());
+    }
+
+    #[test]
+    fn rejects_options_that_need_an_encode() {
+        let crop = CropMode::Manual(CropRect { x: 0, y: 0, width: 1920, height: 800 });
+        let message = rejected(TranscodeOptions { crop: Some(crop), ..Default::default() });
+        assert!(message.contains("crop needs an encoding preset"), "{}", message);
+
+        let tone_mapping = Some(ToneMapping::Hable);
+        let message = rejected(TranscodeOptions { tone_mapping, ..Default::default() });
+        assert!(message.contains("tone mapping"), "{}", message);
+
+        let audio_offset_ms = Some(40);
+        let message = rejected(TranscodeOptions { audio_offset_ms, ..Default::default() });
+        assert!(message.contains("audio offset"), "{}", message);
+
+        let joined = Some(SegmentOutput::Joined { crossfade_sec: None });
+        let message = rejected(TranscodeOptions { segment_output: joined, ..Default::default() });
+        assert!(message.contains("joining segments"), "{}", message);
+    }
+}
@@ -0,0 +1,405 @@
+use crate::error::TranscodeError;
+use crate::models::{Handle, MediaMetadata, SegmentOutput, TimeSegment};
+use std::path::Path;
+
+impl TimeSegment {
+    /// Reject values that can never make a segment, called when the job is submitted
+    pub fn validate(&self) -> Result<(), TranscodeError> {
+        for (name, value) in [("start", Some(self.start_sec)), ("end", self.end_sec)] {
+            if let Some(value) = value.filter(|value| !value.is_finite()) {
+                return Err(TranscodeError::InvalidSegment(format!(
+                    "{} time {} is not a number of seconds",
+                    name, value
+                )));
+            }
+        }
+        for (name, handle) in [("before", &self.handle_before), ("after", &self.handle_after)] {
+            if let Some(Handle::Seconds(seconds)) = handle {
+                if !(seconds.is_finite() && *seconds >= 0.0) {
+                    return Err(TranscodeError::InvalidSegment(format!(
+                        "handle {} {} s must be a number of seconds, zero or more",
+                        name, seconds
+                    )));
+                }
+            }
+        }
+        if self.start_at.is_none() && self.end_at.is_none() {
+            self.check_order()?;
+        }
+        Ok(())
+    }
+
+    /// Segment to transcode from `metadata`, with frame and timecode points
+    /// resolved, the times clamped to the source and the handles added, plus a
+    /// note for each value that moved. Fails when the segment leaves nothing to transcode.
+    pub fn effective(
+        &self,
+        metadata: &MediaMetadata,
+    ) -> Result<(TimeSegment, Vec<String>), TranscodeError> {
+        self.validate()?;
+        let segment = self.resolve(
+            metadata.video.as_ref().map(|video| video.framerate.as_str()),
+            metadata.timecode.as_deref(),
+        )?;
+        segment.check_order()?;
+
+        let duration = metadata.duration_sec;
+        if segment.start_sec >= duration {
+            return Err(TranscodeError::InvalidSegment(format!(
+                "start {} s is past the end of {} ({} s)",
+                format_seconds(segment.start_sec),
+                metadata.file_path,
+                format_seconds(duration)
+            )));
+        }
+
+        let clamped = segment.clamp(duration);
+        let mut corrections = Vec::new();
+        let points = [
+            ("start", Some(segment.start_sec), Some(clamped.start_sec)),
+            ("end", segment.end_sec, clamped.end_sec),
+        ];
+        for (name, requested, effective) in points {
+            if let (Some(requested), Some(effective)) = (requested, effective) {
+                if requested != effective {
+                    corrections.push(format!(
+                        "{} {} s clamped to {} s",
+                        name,
+                        format_seconds(requested),
+                        format_seconds(effective)
+                    ));
+                }
+            }
+        }
+
+        // Handles widen the trim but stop at the ends of the source
+        let before = handle_seconds(&segment.handle_before);
+        let after = handle_seconds(&segment.handle_after);
+        let applied_before = before.min(clamped.start_sec);
+        let applied_after = clamped.end_sec.map_or(0.0, |end| after.min(duration - end));
+        for (name, requested, applied) in [
+            ("before", before, applied_before),
+            ("after", after, applied_after),
+        ] {
+            if applied < requested {
+                corrections.push(format!(
+                    "handle {} {} s shortened to {} s",
+                    name,
+                    format_seconds(requested),
+                    format_seconds(applied)
+                ));
+            }
+        }
+
+        let widened = TimeSegment {
+            start_sec: clamped.start_sec - applied_before,
+            end_sec: clamped.end_sec.map(|end| end + applied_after),
+            start_at: None,
+            end_at: None,
+            handle_before: segment.handle_before.as_ref().map(|_| Handle::Seconds(applied_before)),
+            handle_after: segment.handle_after.as_ref().map(|_| Handle::Seconds(applied_after)),
+        };
+        Ok((widened, corrections))
+    }
+
+    /// Metadata tags recording the handles applied, so a conform can find the in and
+    /// out points. `number` tells apart the segments of a joined output.
+    pub fn handle_tags(&self, number: Option<usize>) -> Vec<(String, String)> {
+        let suffix = number.map(|number| format!("_{}", number)).unwrap_or_default();
+        [("handle_before", &self.handle_before), ("handle_after", &self.handle_after)]
+            .into_iter()
+            .filter_map(|(key, handle)| match handle {
+                Some(Handle::Seconds(seconds)) => {
+                    Some((format!("{}{}", key, suffix), format!("{:.6}", seconds)))
+                }
+                _ => None,
+            })
+            .collect()
+    }
+
+    fn check_order(&self) -> Result<(), TranscodeError> {
+        match self.end_sec {
+            Some(end) if end <= self.start_sec => Err(TranscodeError::InvalidSegment(format!(
+                "end {} s is not after start {} s",
+                format_seconds(end),
+                format_seconds(self.start_sec)
+            ))),
+            _ => Ok(()),
+        }
+    }
+}
+
+impl SegmentOutput {
+    /// Check the options, called when the job is submitted
+    pub fn validate(&self) -> Result<(), TranscodeError> {
+        if let SegmentOutput::Joined { crossfade_sec: Some(crossfade) } = *self {
+            if !(crossfade.is_finite() && crossfade > 0.0) {
+                return Err(TranscodeError::InvalidSegment(format!(
+                    "crossfade {} s must be a positive number of seconds",
+                    crossfade
+                )));
+            }
+        }
+        Ok(())
+    }
+
+    /// Crossfade between joined segments, None for numbered files and straight cuts
+    pub fn crossfade(&self) -> Option<f64> {
+        match *self {
+            SegmentOutput::Joined { crossfade_sec } => crossfade_sec,
+            SegmentOutput::Numbered => None,
+        }
+    }
+}
+
+/// Check a segment list as a whole, called when the job is submitted
+pub fn validate_list(segments: &[TimeSegment]) -> Result<(), TranscodeError> {
+    if segments.is_empty() {
+        return Err(TranscodeError::InvalidSegment("segment list is empty".to_string()));
+    }
+    for segment in segments {
+        segment.validate()?;
+    }
+    Ok(())
+}
+
+/// Path of the `index`th of `count` numbered files: "clip.mov" -> "clip_01.mov"
+pub fn numbered_output_path(output_path: &str, index: usize, count: usize) -> String {
+    let path = Path::new(output_path);
+    let stem = path
+        .file_stem()
+        .map(|stem| stem.to_string_lossy().to_string())
+        .unwrap_or_default();
+    let width = count.to_string().len().max(2);
+    let mut name = format!("{}_{:0width$}", stem, index + 1, width = width);
+    if let Some(extension) = path.extension() {
+        name.push('.');
+        name.push_str(&extension.to_string_lossy());
+    }
+    path.with_file_name(name).to_string_lossy().to_string()
+}
+
+/// Length of the joined output; each crossfade overlaps two segments
+pub fn joined_duration(segments: &[TimeSegment], crossfade: Option<f64>, total: f64) -> f64 {
+    let length: f64 = segments.iter().map(|segment| segment.duration(total)).sum();
+    let overlaps = segments.len().saturating_sub(1) as f64 * crossfade.unwrap_or(0.0);
+    (length - overlaps).max(0.0)
+}
+
+/// Filter graph cutting the source picture into `segments` and joining them
+/// in order, ending in `[vjoin]`
+pub fn video_join_graph(
+    segments: &[TimeSegment],
+    crossfade: Option<f64>,
+    total: f64,
+) -> Vec<String> {
+    let mut graph = Vec::new();
+    let mut parts = Vec::new();
+    for (index, segment) in segments.iter().enumerate() {
+        graph.push(format!(
+            "[0:v]{},setpts=PTS-STARTPTS[vseg{}]",
+            trim_filter("trim", segment),
+            index
+        ));
+        parts.push(format!("[vseg{}]", index));
+    }
+    let fade = |duration: f64, offset: f64| {
+        format!("xfade=transition=fade:duration={:.6}:offset={:.6}", duration, offset)
+    };
+    graph.extend(join_parts(&parts, segments, crossfade, total, "v=1:a=0", fade, "[vjoin]"));
+    graph
+}
+
+/// Filter graph cutting one audio source into `segments` and joining them in
+/// order, ending in `[{label}]`. `filters` put the source on the source timeline
+/// and run before the cuts.
+pub fn audio_join_graph(
+    input: &str,
+    filters: &[String],
+    segments: &[TimeSegment],
+    crossfade: Option<f64>,
+    total: f64,
+    label: &str,
+) -> Vec<String> {
+    let mut graph = Vec::new();
+    let mut parts = Vec::new();
+    for (index, segment) in segments.iter().enumerate() {
+        let mut chain = filters.to_vec();
+        chain.push(trim_filter("atrim", segment));
+        chain.push("asetpts=PTS-STARTPTS".to_string());
+        graph.push(format!("[{}]{}[{}_{}]", input, chain.join(","), label, index));
+        parts.push(format!("[{}_{}]", label, index));
+    }
+    let output = format!("[{}]", label);
+    let fade = |duration: f64, _| format!("acrossfade=d={:.6}", duration);
+    graph.extend(join_parts(&parts, segments, crossfade, total, "v=0:a=1", fade, &output));
+    graph
+}
+
+/// Join the cut `parts` into `output`: one concat of `streams` for straight cuts,
+/// otherwise a chain of pairwise `fade(duration, offset)` filters, each starting
+/// `crossfade` before the cut
+fn join_parts(
+    parts: &[String],
+    segments: &[TimeSegment],
+    crossfade: Option<f64>,
+    total: f64,
+    streams: &str,
+    fade: impl Fn(f64, f64) -> String,
+    output: &str,
+) -> Vec<String> {
+    let Some(crossfade) = crossfade.filter(|_| parts.len() > 1) else {
+        return vec![format!("{}concat=n={}:{}{}", parts.concat(), parts.len(), streams, output)];
+    };
+
+    let mut graph = Vec::new();
+    let mut joined = parts[0].clone();
+    let mut length = segments[0].duration(total);
+    for (index, (part, segment)) in parts.iter().zip(segments).enumerate().skip(1) {
+        let label = if index == parts.len() - 1 {
+            output.to_string()
+        } else {
+            format!("{}_x{}]", output.trim_end_matches(']'), index)
+        };
+        graph.push(format!("{}{}{}{}", joined, part, fade(crossfade, length - crossfade), label));
+        joined = label;
+        length += segment.duration(total) - crossfade;
+    }
+    graph
+}
+
+/// trim or atrim filter cutting out `segment` on the source timeline
+fn trim_filter(filter: &str, segment: &TimeSegment) -> String {
+    match segment.end_sec {
+        Some(end) => format!("{}=start={:.6}:end={:.6}", filter, segment.start_sec, end),
+        None => format!("{}=start={:.6}", filter, segment.start_sec),
+    }
+}
+
+/// Handle length in seconds; frame handles are resolved before this is read
+fn handle_seconds(handle: &Option<Handle>) -> f64 {
+    match handle {
+        Some(Handle::Seconds(seconds)) => *seconds,
+        _ => 0.0,
+    }
+}
+
+/// Seconds to the millisecond without trailing zeros, e.g. "1843.2"
+pub fn format_seconds(seconds: f64) -> String {
+    let text = format!("{:.3}", seconds);
+    text.trim_end_matches('0').trim_end_matches('.').to_string()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn segment(start_sec: f64, end_sec: Option<f64>) -> TimeSegment {
+        TimeSegment {
+            start_sec,
+            end_sec,
+            start_at: None,
+            end_at: None,
+            handle_before: None,
+            handle_after: None,
+        }
+    }
+
+    #[test]
+    fn numbered_output_paths_pad_to_the_count() {
+        assert_eq!(numbered_output_path("/out/clip.mov", 0, 3), "/out/clip_01.mov");
+        assert_eq!(numbered_output_path("/out/clip.mov", 11, 12), "/out/clip_12.mov");
+        assert_eq!(numbered_output_path("/out/clip.mov", 4, 120), "/out/clip_005.mov");
+        assert_eq!(numbered_output_path("/out/clip", 0, 2), "/out/clip_01");
+    }
+
+    #[test]
+    fn joined_duration_subtracts_each_crossfade() {
+        let segments = [segment(0.0, Some(10.0)), segment(20.0, Some(30.0)), segment(50.0, None)];
+        assert_eq!(joined_duration(&segments, None, 60.0), 30.0);
+        assert_eq!(joined_duration(&segments, Some(1.0), 60.0), 28.0);
+        assert_eq!(joined_duration(&segments[..1], Some(1.0), 60.0), 10.0);
+    }
+
+    #[test]
+    fn crossfades_start_before_each_cut() {
+        let segments = [segment(0.0, Some(10.0)), segment(20.0, Some(30.0)), segment(50.0, None)];
+        let graph = video_join_graph(&segments, Some(1.0), 60.0);
+        assert_eq!(graph[0], "[0:v]trim=start=0.000000:end=10.000000,setpts=PTS-STARTPTS[vseg0]");
+        assert_eq!(graph[2], "[0:v]trim=start=50.000000,setpts=PTS-STARTPTS[vseg2]");
+        assert_eq!(
+            graph[3],
+            "[vseg0][vseg1]xfade=transition=fade:duration=1.000000:offset=9.000000[vjoin_x1]"
+        );
+        assert_eq!(
+            graph[4],
+            "[vjoin_x1][vseg2]xfade=transition=fade:duration=1.000000:offset=18.000000[vjoin]"
+        );
+
+        let audio = audio_join_graph("0:a:0", &[], &segments, Some(1.0), 60.0, "aout0");
+        assert_eq!(audio[3], "[aout0_0][aout0_1]acrossfade=d=1.000000[aout0_x1]");
+        assert_eq!(audio[4], "[aout0_x1][aout0_2]acrossfade=d=1.000000[aout0]");
+    }
+
+    #[test]
+    fn straight_cuts_are_one_concat() {
+        let segments = [segment(0.0, Some(10.0)), segment(20.0, Some(30.0))];
+        let graph = video_join_graph(&segments, None, 60.0);
+        assert_eq!(graph.last().unwrap(), "[vseg0][vseg1]concat=n=2:v=1:a=0[vjoin]");
+    }
+
+    fn source(duration_sec: f64) -> MediaMetadata {
+        MediaMetadata {
+            file_path: "/in/clip.mov".to_string(),
+            duration_sec,
+            video: None,
+            audio_streams: Vec::new(),
+            timecode: None,
+            audio_files: Vec::new(),
+        }
+    }
+
+    #[test]
+    fn effective_clamps_the_end_to_the_source() {
+        let (effective, corrections) = segment(10.0, Some(90.0)).effective(&source(60.0)).unwrap();
+        assert_eq!((effective.start_sec, effective.end_sec), (10.0, Some(60.0)));
+        assert_eq!(corrections, vec!["end 90 s clamped to 60 s"]);
+
+        assert!(segment(60.0, None).effective(&source(60.0)).is_err());
+        assert!(segment(20.0, Some(10.0)).effective(&source(60.0)).is_err());
+    }
+
+    #[test]
+    fn effective_shortens_handles_at_the_ends_of_the_source() {
+        let mut requested = segment(2.0, Some(57.0));
+        requested.handle_before = Some(Handle::Seconds(5.0));
+        requested.handle_after = Some(Handle::Seconds(5.0));
+        let (effective, corrections) = requested.effective(&source(60.0)).unwrap();
+
+        assert_eq!((effective.start_sec, effective.end_sec), (0.0, Some(60.0)));
+        assert_eq!(effective.handle_before, Some(Handle::Seconds(2.0)));
+        assert_eq!(effective.handle_after, Some(Handle::Seconds(3.0)));
+        assert_eq!(
+            corrections,
+            vec!["handle before 5 s shortened to 2 s", "handle after 5 s shortened to 3 s"]
+        );
+        assert_eq!(
+            effective.handle_tags(None),
+            vec![
+                ("handle_before".to_string(), "2.000000".to_string()),
+                ("handle_after".to_string(), "3.000000".to_string()),
+            ]
+        );
+    }
+
+    #[test]
+    fn effective_keeps_handles_that_fit() {
+        let mut requested = segment(10.0, Some(20.0));
+        requested.handle_before = Some(Handle::Seconds(1.5));
+        let (effective, corrections) = requested.effective(&source(60.0)).unwrap();
+        assert_eq!((effective.start_sec, effective.end_sec), (8.5, Some(20.0)));
+        assert_eq!(effective.handle_after, None);
+        assert!(corrections.is_empty());
+        assert_eq!(effective.handle_tags(Some(2))[0].0, "handle_before_2");
+    }
+}
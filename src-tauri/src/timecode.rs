@@ -0,0 +1,390 @@
+use crate::error::TranscodeError;
+use crate::models::{Handle, SegmentPosition, TimeSegment};
+use std::fmt;
+
+/// Exact rational frame rate, e.g. 30000/1001 for 29.97
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct FrameRate {
+    pub num: u32,
+    pub den: u32,
+}
+
+impl FrameRate {
+    /// Parse an ffprobe rate such as "30000/1001" or "25/1", or a decimal such
+    /// as "29.97", which is taken as the matching NTSC rate
+    pub fn parse(value: &str) -> Option<Self> {
+        let value = value.trim();
+        let rate = match value.split_once('/') {
+            Some((num, den)) => FrameRate {
+                num: num.trim().parse().ok()?,
+                den: den.trim().parse().ok()?,
+            },
+            None => {
+                let fps: f64 = value.parse().ok()?;
+                if !fps.is_finite() || fps <= 0.0 {
+                    return None;
+                }
+                let ntsc = (fps * 1.001).round();
+                if (fps - fps.round()).abs() < 0.0005 {
+                    FrameRate { num: fps.round() as u32, den: 1 }
+                } else if (fps - ntsc * 1000.0 / 1001.0).abs() < 0.005 {
+                    FrameRate { num: ntsc as u32 * 1000, den: 1001 }
+                } else {
+                    FrameRate { num: (fps * 1000.0).round() as u32, den: 1000 }
+                }
+            }
+        };
+        if rate.num == 0 || rate.den == 0 {
+            return None;
+        }
+        Some(rate)
+    }
+
+    pub fn fps(self) -> f64 {
+        self.num as f64 / self.den as f64
+    }
+
+    /// Nominal timecode rate, e.g. 30 for 30000/1001
+    pub fn nominal(self) -> u32 {
+        (self.fps().round() as u32).max(1)
+    }
+
+    /// Whether drop-frame timecode exists at this rate: 29.97 and 59.94 only
+    pub fn allows_drop_frame(self) -> bool {
+        self == FrameRate { num: 30000, den: 1001 } || self == FrameRate { num: 60000, den: 1001 }
+    }
+
+    /// Exact duration of a number of frames
+    pub fn frames_to_seconds(self, frames: u64) -> f64 {
+        frames as f64 * self.den as f64 / self.num as f64
+    }
+
+    /// Frame containing a point in time, rounded to the nearest frame
+    pub fn seconds_to_frames(self, seconds: f64) -> u64 {
+        (seconds.max(0.0) * self.num as f64 / self.den as f64).round() as u64
+    }
+}
+
+impl fmt::Display for FrameRate {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}/{}", self.num, self.den)
+    }
+}
+
+/// SMPTE timecode at a nominal integer frame rate (30 for 29.97, 24 for 23.976)
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Timecode {
+    pub hours: u32,
+    pub minutes: u32,
+    pub seconds: u32,
+    pub frames: u32,
+    /// Drop-frame counting (29.97/59.94), written with ';' before the frames
+    pub drop_frame: bool,
+}
+
+impl Timecode {
+    /// Parse "HH:MM:SS:FF" (non-drop) or "HH:MM:SS;FF" / "HH:MM:SS.FF" (drop-frame)
+    pub fn parse(value: &str) -> Option<Self> {
+        let value = value.trim();
+        let drop_frame = value.contains(';') || value.contains('.');
+        let parts: Vec<&str> = value.split([':', ';', '.']).collect();
+        if parts.len() != 4 {
+            return None;
+        }
+
+        let hours: u32 = parts[0].parse().ok()?;
+        let minutes: u32 = parts[1].parse().ok()?;
+        let seconds: u32 = parts[2].parse().ok()?;
+        let frames: u32 = parts[3].parse().ok()?;
+        if minutes >= 60 || seconds >= 60 {
+            return None;
+        }
+
+        Some(Timecode { hours, minutes, seconds, frames, drop_frame })
+    }
+
+    /// Check the timecode exists at `rate`: the frames fit in a second, drop-frame
+    /// is only used at 29.97 and 59.94, and no label skipped by drop-frame is named
+    pub fn check(self, rate: FrameRate) -> Result<Self, TranscodeError> {
+        let fps = rate.nominal();
+        if self.frames >= fps {
+            return Err(TranscodeError::InvalidSegment(format!(
+                "invalid timecode {} at {} fps",
+                self, fps
+            )));
+        }
+        if !self.drop_frame {
+            return Ok(self);
+        }
+        if !rate.allows_drop_frame() {
+            return Err(TranscodeError::InvalidSegment(format!(
+                "drop-frame timecode {} needs a 29.97 or 59.94 fps source, not {}",
+                self, rate
+            )));
+        }
+        let dropped = drop_frames_per_minute(fps as u64) as u32;
+        if self.seconds == 0 && !self.minutes.is_multiple_of(10) && self.frames < dropped {
+            return Err(TranscodeError::InvalidSegment(format!(
+                "timecode {} does not exist, drop-frame skips frames 00 to {:02} \
+at the start of every minute except every tenth",
+                self,
+                dropped - 1
+            )));
+        }
+        Ok(self)
+    }
+
+    /// Frame count since 00:00:00:00
+    pub fn to_frames(self, fps: u32) -> u64 {
+        let fps = fps as u64;
+        let total_minutes = self.hours as u64 * 60 + self.minutes as u64;
+        let frames = (total_minutes * 60 + self.seconds as u64) * fps + self.frames as u64;
+
+        if self.drop_frame {
+            // Two frame numbers (four at 60p) are skipped every minute except every tenth
+            let dropped = drop_frames_per_minute(fps) * (total_minutes - total_minutes / 10);
+            frames.saturating_sub(dropped)
+        } else {
+            frames
+        }
+    }
+
+    /// Build a timecode from a frame count, wrapping at 24 hours
+    pub fn from_frames(frames: u64, fps: u32, drop_frame: bool) -> Self {
+        let fps = fps.max(1) as u64;
+        let mut frames = frames;
+
+        if drop_frame {
+            let drop = drop_frames_per_minute(fps);
+            let frames_per_minute = fps * 60 - drop;
+            let frames_per_10_minutes = fps * 600 - drop * 9;
+            let frames_per_day = frames_per_10_minutes * 6 * 24;
+            frames %= frames_per_day;
+
+            let tens = frames / frames_per_10_minutes;
+            let remainder = frames % frames_per_10_minutes;
+            frames += drop * 9 * tens;
+            if remainder > drop {
+                frames += drop * ((remainder - drop) / frames_per_minute);
+            }
+        } else {
+            frames %= fps * 86400;
+        }
+
+        Timecode {
+            hours: (frames / (fps * 3600)) as u32,
+            minutes: ((frames / (fps * 60)) % 60) as u32,
+            seconds: ((frames / fps) % 60) as u32,
+            frames: (frames % fps) as u32,
+            drop_frame,
+        }
+    }
+
+    /// Advance by a number of seconds of real time at `framerate`. Drop-frame
+    /// counting is kept only when `framerate` is 29.97 or 59.94.
+    pub fn offset_by_seconds(self, seconds: f64, framerate: &str) -> Self {
+        let rate = FrameRate::parse(framerate).unwrap_or(FrameRate { num: 25, den: 1 });
+        let drop_frame = self.drop_frame && rate.allows_drop_frame();
+        Timecode::from_seconds(self.to_seconds(rate) + seconds.max(0.0), rate, drop_frame)
+    }
+
+    /// Real time since 00:00:00:00 at an exact rate
+    pub fn to_seconds(self, rate: FrameRate) -> f64 {
+        rate.frames_to_seconds(self.to_frames(rate.nominal()))
+    }
+
+    /// Timecode at a point in real time since 00:00:00:00, wrapping at 24 hours
+    pub fn from_seconds(seconds: f64, rate: FrameRate, drop_frame: bool) -> Self {
+        Timecode::from_frames(rate.seconds_to_frames(seconds), rate.nominal(), drop_frame)
+    }
+}
+
+impl TimeSegment {
+    /// Convert frame number and timecode positions and frame handles to seconds,
+    /// called when the job starts. Timecodes are source timecodes, counted from `start_timecode`
+    /// (or 00:00:00:00 when the source has none).
+    pub fn resolve(
+        &self,
+        framerate: Option<&str>,
+        start_timecode: Option<&str>,
+    ) -> Result<TimeSegment, TranscodeError> {
+        let rate = || {
+            framerate.and_then(FrameRate::parse).ok_or_else(|| {
+                TranscodeError::InvalidSegment(
+                    "frame numbers and timecodes need a video source".to_string(),
+                )
+            })
+        };
+        let resolve = |position: &SegmentPosition| -> Result<f64, TranscodeError> {
+            let rate = rate()?;
+            match position {
+                SegmentPosition::Frame(frame) => Ok(rate.frames_to_seconds(*frame)),
+                SegmentPosition::Timecode(value) => {
+                    let timecode = Timecode::parse(value)
+                        .ok_or_else(|| {
+                            TranscodeError::InvalidSegment(format!(
+                                "invalid timecode {} at {} fps",
+                                value,
+                                rate.nominal()
+                            ))
+                        })?
+                        .check(rate)?;
+                    let origin = start_timecode
+                        .and_then(Timecode::parse)
+                        .map_or(0.0, |start| start.to_seconds(rate));
+                    let seconds = timecode.to_seconds(rate) - origin;
+                    if seconds < 0.0 {
+                        return Err(TranscodeError::InvalidSegment(format!(
+                            "timecode {} is before the source starts at {}",
+                            value,
+                            start_timecode.unwrap_or_default()
+                        )));
+                    }
+                    Ok(seconds)
+                }
+            }
+        };
+
+        let start_sec = match &self.start_at {
+            Some(position) => resolve(position)?,
+            None => self.start_sec,
+        };
+        let end_sec = match &self.end_at {
+            Some(position) => Some(resolve(position)?),
+            None => self.end_sec,
+        };
+        let resolve_handle = |handle: &Option<Handle>| -> Result<Option<Handle>, TranscodeError> {
+            match handle {
+                Some(Handle::Frames(frames)) => {
+                    Ok(Some(Handle::Seconds(rate()?.frames_to_seconds(*frames))))
+                }
+                other => Ok(other.clone()),
+            }
+        };
+        Ok(TimeSegment {
+            start_sec,
+            end_sec,
+            start_at: None,
+            end_at: None,
+            handle_before: resolve_handle(&self.handle_before)?,
+            handle_after: resolve_handle(&self.handle_after)?,
+        })
+    }
+}
+
+impl fmt::Display for Timecode {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let separator = if self.drop_frame { ';' } else { ':' };
+        write!(
+            f,
+            "{:02}:{:02}:{:02}{}{:02}",
+            self.hours, self.minutes, self.seconds, separator, self.frames
+        )
+    }
+}
+
+/// Parse an ffprobe frame rate such as "30000/1001" or "25"
+pub fn parse_framerate(framerate: &str) -> Option<f64> {
+    FrameRate::parse(framerate).map(FrameRate::fps)
+}
+
+/// Standard editing rates as (rational, fps)
+const STANDARD_RATES: [(&str, f64); 8] = [
+    ("24000/1001", 24000.0 / 1001.0),
+    ("24/1", 24.0),
+    ("25/1", 25.0),
+    ("30000/1001", 30000.0 / 1001.0),
+    ("30/1", 30.0),
+    ("50/1", 50.0),
+    ("60000/1001", 60000.0 / 1001.0),
+    ("60/1", 60.0),
+];
+
+/// Closest standard editing rate, e.g. 29.6 (a VFR phone clip) -> "30000/1001"
+pub fn nearest_standard_rate(fps: f64) -> &'static str {
+    STANDARD_RATES
+        .iter()
+        .min_by(|a, b| (a.1 - fps).abs().total_cmp(&(b.1 - fps).abs()))
+        .map(|(rate, _)| *rate)
+        .unwrap_or("25/1")
+}
+
+/// Frame numbers skipped per minute in drop-frame counting: 2 at 29.97, 4 at
+/// 59.94. Other rates have no drop-frame counting and skip none.
+fn drop_frames_per_minute(fps: u64) -> u64 {
+    match fps {
+        30 => 2,
+        60 => 4,
+        _ => 0,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const NTSC_30: FrameRate = FrameRate { num: 30000, den: 1001 };
+    const NTSC_60: FrameRate = FrameRate { num: 60000, den: 1001 };
+
+    fn round_trips(fps: u32, drop_frame: bool) {
+        // Every frame of the first eleven minutes, then samples across the day
+        let frames = (0..fps as u64 * 660).chain((0..86_400).map(|n| n * fps as u64 - n));
+        for frame in frames {
+            let timecode = Timecode::from_frames(frame, fps, drop_frame);
+            assert_eq!(timecode.to_frames(fps), frame, "{} at {} fps", timecode, fps);
+            assert_eq!(Timecode::parse(&timecode.to_string()), Some(timecode));
+        }
+    }
+
+    #[test]
+    fn non_drop_frame_round_trips() {
+        for fps in [24, 25, 30, 60] {
+            round_trips(fps, false);
+        }
+    }
+
+    #[test]
+    fn drop_frame_round_trips() {
+        round_trips(30, true);
+        round_trips(60, true);
+    }
+
+    #[test]
+    fn drop_frame_skips_labels_except_every_tenth_minute() {
+        assert_eq!(Timecode::from_frames(1800, 30, true).to_string(), "00:01:00;02");
+        assert_eq!(Timecode::from_frames(17982, 30, true).to_string(), "00:10:00;00");
+        assert_eq!(Timecode::from_frames(3600, 60, true).to_string(), "00:01:00;04");
+        assert_eq!(Timecode::from_frames(107_892, 30, true).to_string(), "01:00:00;00");
+    }
+
+    #[test]
+    fn check_rejects_skipped_labels() {
+        let parse = |value: &str| Timecode::parse(value).unwrap();
+        assert!(parse("00:01:00;00").check(NTSC_30).is_err());
+        assert!(parse("00:01:00;01").check(NTSC_30).is_err());
+        assert!(parse("00:01:00;02").check(NTSC_30).is_ok());
+        assert!(parse("00:10:00;00").check(NTSC_30).is_ok());
+        assert!(parse("00:01:00;03").check(NTSC_60).is_err());
+        assert!(parse("00:01:00;04").check(NTSC_60).is_ok());
+    }
+
+    #[test]
+    fn check_limits_drop_frame_to_ntsc_rates() {
+        let timecode = Timecode::parse("00:00:10;00").unwrap();
+        assert!(timecode.check(NTSC_30).is_ok());
+        assert!(timecode.check(NTSC_60).is_ok());
+        assert!(timecode.check(FrameRate { num: 25, den: 1 }).is_err());
+        assert!(timecode.check(FrameRate { num: 24000, den: 1001 }).is_err());
+        assert!(timecode.check(FrameRate { num: 30, den: 1 }).is_err());
+        let past_last_frame = Timecode::parse("00:00:10:25").unwrap();
+        assert!(past_last_frame.check(FrameRate { num: 25, den: 1 }).is_err());
+    }
+
+    #[test]
+    fn offset_keeps_drop_frame_only_at_ntsc_rates() {
+        let start = Timecode::parse("01:00:00;00").unwrap();
+        assert_eq!(start.offset_by_seconds(10.0, "25").to_string(), "01:00:10:00");
+        assert_eq!(start.offset_by_seconds(0.0, "30000/1001").to_string(), "01:00:00;00");
+        assert_eq!(start.offset_by_seconds(0.0, "60000/1001").to_string(), "01:00:00;00");
+    }
+}
@@ -0,0 +1,73 @@
+use crate::error::TranscodeError;
+use crate::ffmpeg::ffprobe;
+use crate::models::{Watermark, WatermarkAnchor};
+
+const DEFAULT_SCALE: f64 = 0.15;
+const DEFAULT_OPACITY: f64 = 1.0;
+
+impl Watermark {
+    /// Check the options and that the image decodes, called when the job is submitted
+    pub async fn validate(&self) -> Result<(), TranscodeError> {
+        if let Some(scale) = self.scale {
+            if !(scale > 0.0 && scale <= 1.0) {
+                return Err(TranscodeError::InvalidOption(format!(
+                    "watermark scale {} must be between 0 and 1 of the video width",
+                    scale
+                )));
+            }
+        }
+        if let Some(opacity) = self.opacity {
+            if !(0.0..=1.0).contains(&opacity) {
+                return Err(TranscodeError::InvalidOption(format!(
+                    "watermark opacity {} must be between 0 and 1",
+                    opacity
+                )));
+            }
+        }
+        if !std::path::Path::new(&self.path).is_file() {
+            return Err(TranscodeError::InvalidOption(format!(
+                "watermark not found: {}",
+                self.path
+            )));
+        }
+
+        // ffprobe reports a still image as a single-frame video stream
+        let metadata = ffprobe::extract_metadata(&self.path).await.map_err(|e| {
+            TranscodeError::InvalidOption(format!("watermark is not a decodable image: {}", e))
+        })?;
+        if !metadata.video.is_some_and(|video| video.width > 0 && video.height > 0) {
+            return Err(TranscodeError::InvalidOption(format!(
+                "watermark has no picture: {}",
+                self.path
+            )));
+        }
+
+        Ok(())
+    }
+
+    /// Filters for the watermark input: scale to the video width and apply opacity
+    pub fn input_filter(&self, video_width: u32) -> String {
+        let width = (video_width as f64 * self.scale.unwrap_or(DEFAULT_SCALE)).round() as u32;
+        // Keep the width even so subsampled formats do not need padding
+        let width = (width.max(2) / 2) * 2;
+        format!(
+            "scale={}:-1,format=rgba,colorchannelmixer=aa={:.3}",
+            width,
+            self.opacity.unwrap_or(DEFAULT_OPACITY)
+        )
+    }
+
+    /// Overlay filter placing the watermark at its anchor.
+    /// The single image is repeated for the whole clip (overlay's default eof_action).
+    pub fn overlay_filter(&self) -> String {
+        // Margin of 3% of the frame width from the edges
+        let (x, y) = match self.anchor {
+            WatermarkAnchor::TopLeft => ("W*0.03", "W*0.03"),
+            WatermarkAnchor::TopRight => ("W-w-W*0.03", "W*0.03"),
+            WatermarkAnchor::BottomLeft => ("W*0.03", "H-h-W*0.03"),
+            WatermarkAnchor::BottomRight => ("W-w-W*0.03", "H-h-W*0.03"),
+            WatermarkAnchor::Center => ("(W-w)/2", "(H-h)/2"),
+        };
+        format!("overlay=x={}:y={}:format=auto", x, y)
+    }
+}
@@ -40,10 +40,17 @@ const VIDEO_EXTENSIONS = [
   "mp4", "mkv", "avi", "mov", "m4v", "webm", "flv", "wmv"
 ];
 
-// Check if a path is a video file based on extension
-function isVideoFile(path: string): boolean {
+// Supported audio-only file extensions (transcoded with the audio presets)
+const AUDIO_EXTENSIONS = [
+  "wav", "bwf", "mp3", "flac", "m4a", "aif", "aiff"
+];
+
+const MEDIA_EXTENSIONS = [...VIDEO_EXTENSIONS, ...AUDIO_EXTENSIONS];
+
+// Check if a path is a video or audio file based on extension
+function isMediaFile(path: string): boolean {
   const ext = path.split(".").pop()?.toLowerCase();
-  return ext ? VIDEO_EXTENSIONS.includes(ext) : false;
+  return ext ? MEDIA_EXTENSIONS.includes(ext) : false;
 }
 
 // Extract file name from path
@@ -58,12 +65,12 @@ function getBaseFileName(path: string): string {
   return lastDotIndex > 0 ? fileName.slice(0, lastDotIndex) : fileName;
 }
 
-// Read video files from a folder (non-recursive)
+// Read video and audio files from a folder (non-recursive)
 async function readVideoFilesFromFolder(folderPath: string): Promise<string[]> {
   try {
     const entries = await readDir(folderPath);
     const videoFiles = entries
-      .filter(entry => !entry.children && entry.path && isVideoFile(entry.path))
+      .filter(entry => !entry.children && entry.path && isMediaFile(entry.path))
       .map(entry => entry.path);
     return videoFiles;
   } catch (error) {
@@ -199,7 +206,7 @@ function App() {
     });
 
     const unlistenError = listen(TAURI_EVENTS.BATCH_ERROR, (event) => {
-      const [batch_id, file_index] = event.payload as [string, number];
+      const [batch_id, file_index, error] = event.payload as [string, number, string];
 
       if (currentBatchId && batch_id === currentBatchId) {
         setTasks((prevTasks) => {
@@ -208,6 +215,7 @@ function App() {
             newTasks[file_index] = {
               ...newTasks[file_index],
               status: "failed",
+              error,
             };
           }
           return newTasks;
@@ -293,7 +301,7 @@ function App() {
       const allVideoFiles: string[] = [];
 
       for (const path of droppedPaths) {
-        if (isVideoFile(path)) {
+        if (isMediaFile(path)) {
           allVideoFiles.push(path);
         } else {
           const videoFiles = await readVideoFilesFromFolder(path);
@@ -426,8 +434,8 @@ function App() {
         multiple: true,
         filters: [
           {
-            name: "Video Files",
-            extensions: VIDEO_EXTENSIONS,
+            name: "Media Files",
+            extensions: MEDIA_EXTENSIONS,
           },
         ],
       });
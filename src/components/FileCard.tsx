@@ -186,7 +186,9 @@ export function FileCard({
 
           {/* Failed indicator */}
           {task.status === "failed" && (
-            <div className="file-card-error">Transcoding failed</div>
+            <div className="file-card-error" title={task.error}>
+              {task.error ? `Transcoding failed: ${task.error}` : "Transcoding failed"}
+            </div>
           )}
         </div>
 
@@ -253,24 +255,49 @@ export function FileCard({
             <div className="file-card-expanded-section">
               <span className="section-label">File Info</span>
               <div className="file-card-metadata-grid">
-                <span className="metadata-item">
-                  {metadata.video.width}×{metadata.video.height}
-                </span>
-                <span className="metadata-item">
-                  {metadata.video.framerate} fps
-                </span>
+                {metadata.video && (
+                  <>
+                    <span className="metadata-item">
+                      {metadata.video.width}×{metadata.video.height}
+                    </span>
+                    <span className="metadata-item">
+                      {metadata.video.framerate} fps
+                    </span>
+                  </>
+                )}
                 <span className="metadata-item">
                   {secondsToTimecode(metadata.duration_sec)}
                 </span>
-                <span className="metadata-item">
-                  {metadata.video.codec}
-                </span>
-                <span className="metadata-item">
-                  {metadata.video.bit_depth}-bit
-                </span>
-                <span className="metadata-item">
-                  {metadata.video.chroma_subsampling}
-                </span>
+                {metadata.video ? (
+                  <>
+                    <span className="metadata-item">
+                      {metadata.video.codec}
+                    </span>
+                    <span className="metadata-item">
+                      {metadata.video.bit_depth}-bit
+                    </span>
+                    <span className="metadata-item">
+                      {metadata.video.chroma_subsampling}
+                    </span>
+                  </>
+                ) : metadata.audio_streams.length > 0 && (
+                  <>
+                    <span className="metadata-item">
+                      {metadata.audio_streams[0].codec}
+                    </span>
+                    <span className="metadata-item">
+                      {metadata.audio_streams[0].sample_rate / 1000} kHz
+                    </span>
+                    <span className="metadata-item">
+                      {metadata.audio_streams[0].channels} ch
+                    </span>
+                  </>
+                )}
+                {metadata.audio_streams.length > 1 && (
+                  <span className="metadata-item">
+                    {metadata.audio_streams.length} audio tracks
+                  </span>
+                )}
               </div>
             </div>
           )}
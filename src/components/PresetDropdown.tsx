@@ -17,6 +17,10 @@ const PRESET_ORDER: OutputPreset[] = [
   "ProRes422Proxy",
   "DnxHRHQX",
   "H264Crf18",
+  "WavPcm24",
+  "Flac",
+  "Aac",
+  "Remux",
 ];
 
 export function PresetDropdown({
@@ -2,4 +2,7 @@ export const TAURI_EVENTS = {
   BATCH_PROGRESS: "batch-transcode-progress",
   BATCH_COMPLETE: "batch-transcode-complete",
   BATCH_ERROR: "batch-transcode-error",
+  OFFLOAD_PROGRESS: "offload-progress",
+  OFFLOAD_COMPLETE: "offload-complete",
+  OFFLOAD_ERROR: "offload-error",
 } as const;
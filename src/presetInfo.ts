@@ -45,6 +45,34 @@ export const PRESET_PARAMETERS: Record<OutputPreset, PresetParameters> = {
     chroma: "4:2:0",
     bitrate: "~25 Mbps @1080p"
   },
+  WavPcm24: {
+    codec: "None (audio only)",
+    audio: "PCM 24-bit 48 kHz",
+    colorDepth: "-",
+    chroma: "-",
+    bitrate: "~2.3 Mbps stereo"
+  },
+  Flac: {
+    codec: "None (audio only)",
+    audio: "FLAC lossless",
+    colorDepth: "-",
+    chroma: "-",
+    bitrate: "~1.4 Mbps stereo"
+  },
+  Aac: {
+    codec: "None (audio only)",
+    audio: "AAC 320kbps",
+    colorDepth: "-",
+    chroma: "-",
+    bitrate: "320 kbps"
+  },
+  Remux: {
+    codec: "Copied from source",
+    audio: "Copied from source",
+    colorDepth: "Source",
+    chroma: "Source",
+    bitrate: "Source"
+  },
 };
 
 // Preset information for UI display
@@ -78,6 +106,26 @@ export const PRESET_INFO: Record<OutputPreset, {
     description: "H.264, 8-bit, 4:2:0, AAC 320kbps",
     bitrateMbps: PRESET_BITRATE.H264Crf18, // 25 Mbps at 1080p (variable bitrate, CRF-based)
   },
+  WavPcm24: {
+    name: PRESET_DISPLAY_NAMES.WavPcm24,
+    description: "Audio only, WAV PCM 24-bit 48 kHz",
+    bitrateMbps: PRESET_BITRATE.WavPcm24,
+  },
+  Flac: {
+    name: PRESET_DISPLAY_NAMES.Flac,
+    description: "Audio only, FLAC lossless",
+    bitrateMbps: PRESET_BITRATE.Flac,
+  },
+  Aac: {
+    name: PRESET_DISPLAY_NAMES.Aac,
+    description: "Audio only, AAC 320kbps",
+    bitrateMbps: PRESET_BITRATE.Aac,
+  },
+  Remux: {
+    name: PRESET_DISPLAY_NAMES.Remux,
+    description: "Rewrap into MOV without re-encoding",
+    bitrateMbps: PRESET_BITRATE.Remux,
+  },
 };
 
 // Re-export preset-related types and constants for convenience
@@ -3,26 +3,74 @@ export interface MediaMetadata {
   duration_sec: number;
   video: {
     codec: string;
-    width: number;
-    height: number;
+    width: number;           // Coded width
+    height: number;          // Coded height
+    rotation: number;        // Clockwise display rotation: 0, 90, 180 or 270
+    display_width: number;   // Width as displayed, after rotation
+    display_height: number;  // Height as displayed, after rotation
     framerate: string;
+    avg_framerate: string;   // Differs from framerate for VFR clips
+    is_vfr: boolean;
     bit_depth: number;
     pix_fmt: string;
     chroma_subsampling: string;
-  };
-  audio?: {
-    codec: string;
-    sample_rate: number;
-    channels: number;
-  };
+    color_primaries: string | null;  // e.g. "bt709", "bt2020"
+    color_transfer: string | null;   // e.g. "bt709", "smpte2084"
+    color_space: string | null;      // e.g. "bt709", "bt2020nc"
+    color_range: string | null;      // "tv" (limited) or "pc" (full)
+    hdr_format: HdrFormat | null;    // null for SDR
+    hdr_metadata: HdrMetadata | null;
+  } | null;                          // null for audio-only files
+  audio_streams: AudioStream[];      // Every audio stream, in file order
+  timecode: string | null;           // Start timecode from tmcd, video stream or container, e.g. "01:00:00:00"
+  audio_files: string[];             // One per audio stream when the picture file has none (P2)
+}
+
+export interface AudioStream {
+  index: number;            // Position among audio streams, used by audio_tracks
+  codec: string;
+  sample_rate: number;
+  channels: number;
+  sample_fmt: string;       // Decoded sample format, e.g. "s16", "s32", "fltp"
+  bit_depth: number;        // Source bits per sample, e.g. 24
+  language: string | null;
+  title: string | null;
+}
+
+export type HdrFormat = "Pq" | "Hlg";
+
+export interface HdrMetadata {
+  mastering_display: string | null;  // x265 notation, e.g. "G(13250,34500)B(...)R(...)WP(...)L(...)"
+  max_cll: number | null;
+  max_fall: number | null;
 }
 
 export interface TimeSegment {
   start_sec: number;  // Start time in seconds
   end_sec: number | null;  // End time in seconds, null means end of video
+  start_at?: SegmentPosition;  // Frame or source timecode, used instead of start_sec
+  end_at?: SegmentPosition;  // Frame or source timecode, used instead of end_sec
+  handle_before?: Handle;  // Extra material before the start, stops at the start of the source
+  handle_after?: Handle;  // Extra material after the end, stops at the end of the source
 }
 
-export type OutputPreset = "ProRes422" | "ProRes422LT" | "ProRes422Proxy" | "DnxHRHQX" | "H264Crf18";
+// Segments reported back hold the handles applied in Seconds, and include them in start/end
+export type Handle = { Seconds: number } | { Frames: number };
+
+// Frame number from 0, or source timecode "HH:MM:SS:FF" ("HH:MM:SS;FF" drop-frame)
+export type SegmentPosition = { Frame: number } | { Timecode: string };
+
+export type OutputPreset =
+  | "ProRes422" | "ProRes422LT" | "ProRes422Proxy" | "DnxHRHQX" | "H264Crf18"
+  // Audio only - with a video input these extract the sound
+  | "WavPcm24" | "Flac" | "Aac"
+  // Stream copy into the container the output extension names (.mov, .mp4, .mkv)
+  | "Remux";
+
+/** Presets that write audio alone */
+export function isAudioPreset(preset: OutputPreset): boolean {
+  return preset === "WavPcm24" || preset === "Flac" || preset === "Aac";
+}
 
 // Preset bitrate information for size estimation
 export const PRESET_BITRATE: Record<OutputPreset, number> = {
@@ -31,10 +79,17 @@ export const PRESET_BITRATE: Record<OutputPreset, number> = {
   ProRes422Proxy: 36,
   DnxHRHQX: 295,
   H264Crf18: 25,       // Variable bitrate, CRF-based
+  WavPcm24: 2.3,       // Stereo 24-bit 48 kHz, not resolution dependent
+  Flac: 1.4,           // Typical lossless compression of 24-bit stereo
+  Aac: 0.32,
+  Remux: 0,            // Same as the source
 };
 
 /** Get output file suffix and extension for a preset */
-export function getPresetOutputInfo(preset: OutputPreset): { suffix: string; ext: ".mov" | ".mp4" } {
+export function getPresetOutputInfo(preset: OutputPreset): {
+  suffix: string;
+  ext: ".mov" | ".mp4" | ".wav" | ".flac" | ".m4a";
+} {
   switch (preset) {
     case "ProRes422LT":
       return { suffix: "_proreslt", ext: ".mov" };
@@ -44,16 +99,155 @@ export function getPresetOutputInfo(preset: OutputPreset): { suffix: string; ext
       return { suffix: "_proxy", ext: ".mov" };
     case "H264Crf18":
       return { suffix: "_h264", ext: ".mp4" };
+    case "WavPcm24":
+      return { suffix: "_audio", ext: ".wav" };
+    case "Flac":
+      return { suffix: "_audio", ext: ".flac" };
+    case "Aac":
+      return { suffix: "_audio", ext: ".m4a" };
+    case "Remux":
+      return { suffix: "_remux", ext: ".mov" };
     default:
       return { suffix: "_prores", ext: ".mov" };
   }
 }
 
-export interface TranscodeRequest {
+export type ColorStandard = "Rec601" | "Rec709" | "Rec2020";
+export type ColorRange = "Limited" | "Full";
+
+/** HDR to SDR tone mapping; omit for automatic tone mapping on proxy/review presets */
+export type ToneMapping = "Off" | "Hable" | "Mobius" | "Reinhard";
+
+export type LutInterpolation = "Nearest" | "Trilinear" | "Tetrahedral";
+
+/** 3D LUT (.cube) baked into the output */
+export interface LutOptions {
+  path: string;
+  interpolation: LutInterpolation | null;  // null = tetrahedral
+}
+
+export type BurnInPosition =
+  | "TopLeft" | "TopCenter" | "TopRight"
+  | "BottomLeft" | "BottomCenter" | "BottomRight";
+
+/** Burn-in content; free text supports {filename}, {frame} and {time} tokens */
+export type BurnInContent = "Timecode" | "Filename" | "FrameCounter" | { Text: string };
+
+export interface BurnInElement {
+  content: BurnInContent;
+  position: BurnInPosition;
+}
+
+/** Text burned into the picture */
+export interface BurnIn {
+  elements: BurnInElement[];
+  size: number | null;         // Fraction of frame height, null = 0.035
+  box_opacity: number | null;  // 0-1, null = 0.5, 0 disables the box
+  font_file: string | null;    // null = monospace system font
+}
+
+/** Explicit colour conversion; omitted fields keep the source value */
+export interface ColorConversion {
+  standard: ColorStandard | null;
+  range: ColorRange | null;
+}
+
+export type WatermarkAnchor = "TopLeft" | "TopRight" | "BottomLeft" | "BottomRight" | "Center";
+
+/** Image watermark overlaid on the picture */
+export interface Watermark {
+  path: string;
+  anchor: WatermarkAnchor;
+  scale: number | null;    // Fraction of video width, null = 0.15
+  opacity: number | null;  // 0-1, null = 1
+}
+
+/** Crop rectangle in source pixels */
+export interface CropRect {
+  x: number;
+  y: number;
+  width: number;
+  height: number;
+}
+
+/** "Auto" detects black bars per file when the job starts */
+export type CropMode = "Auto" | { Manual: CropRect };
+
+/** Stereo downmix coefficients for 5.1 / 7.1 sources (linear gains) */
+export type DownmixCoefficients =
+  | "Itu"   // ITU-R BS.775 Lo/Ro
+  | "LtRt"  // Matrix-encoded, Pro Logic II compatible
+  | { Custom: { center: number; surround: number; lfe: number } };
+
+/** Channel remapping; channels of the selected tracks are numbered in order */
+export type AudioLayout =
+  | "SplitMono"
+  | "Merge"
+  | { StereoDownmix: DownmixCoefficients }
+  | { Custom: number[][] };  // Source channels for each output track
+
+export type AudioBitDepth = "Int16" | "Int24" | "Float32";
+
+/** Output audio format; omitted fields use the preset default (48 kHz except FLAC) */
+export interface AudioFormat {
+  sample_rate?: number;
+  bit_depth?: AudioBitDepth;  // PCM and FLAC presets only
+}
+
+/** Sync offset in seconds or in frames of the source video */
+export type SyncOffset = { Seconds: number } | { Frames: number };
+
+/** Audio from a separate recorder (double-system sound) */
+export interface ExternalAudio {
+  path: string;
+  mode: "Replace" | "Add";  // Add puts it after the source tracks
+  offset?: SyncOffset;      // Where the recording starts on the source timeline; omit when both start together
+}
+
+/** Two-pass loudness target (EBU R128: -23 / -1, ATSC A/85: -24 / -2) */
+export interface LoudnessNormalization {
+  integrated_lufs: number;
+  true_peak_dbtp: number;
+  lra: number;
+}
+
+/** Values measured by the loudnorm analysis pass (null for -inf on silence) */
+export interface LoudnessMeasurement {
+  input_i: number | null;
+  input_tp: number | null;
+  input_lra: number | null;
+  input_thresh: number | null;
+  target_offset: number | null;
+}
+
+/** Processing options shared by single and batch requests (flattened into both) */
+export interface TranscodeOptions {
+  frame_rate?: string;  // Optional CFR target, e.g. "25" or "30000/1001"; VFR sources convert automatically
+  crop?: CropMode;  // Optional
+  color_conversion?: ColorConversion;  // Optional, default tags output to match source
+  tone_mapping?: ToneMapping;  // Optional
+  lut?: LutOptions;  // Optional
+  burn_in?: BurnIn;  // Optional
+  watermark?: Watermark;  // Optional
+  loudness?: LoudnessNormalization;  // Optional, applied to the first audio track
+  audio_tracks?: number[];  // Optional audio stream indices in output order; omit for all, [] drops audio
+  audio_layout?: AudioLayout;  // Optional
+  external_audio?: ExternalAudio;  // Optional
+  audio_offset_ms?: number;  // Optional sync correction: positive delays the audio, negative advances it
+  audio_format?: AudioFormat;  // Optional
+  segment_output?: SegmentOutput;  // Optional, how a segment list is written; default Numbered
+  checksum?: ChecksumOptions;  // Optional hashes of the source and output
+}
+
+/** Numbered: one file per segment ("clip_01.mov"...). Joined: one output, optionally crossfaded */
+export type SegmentOutput = "Numbered" | { Joined: { crossfade_sec?: number } };
+
+export interface TranscodeRequest extends TranscodeOptions {
   input_path: string;
   output_path: string;
   preset: OutputPreset;
   segment?: TimeSegment;  // Optional
+  segment_list?: TimeSegment[];  // Optional, used instead of segment
 }
 
 export interface TranscodeProgress {
@@ -65,6 +259,49 @@ export interface TranscodeProgress {
   estimated_time?: string;
 }
 
+/** Sent with completion events: single jobs emit it directly, batch jobs as [batch_id, file_index, result] */
+export interface TranscodeResult {
+  input_path: string;
+  output_path: string;
+  loudness: LoudnessMeasurement | null;
+  segment: TimeSegment | null;  // Segment actually transcoded, in seconds
+  segment_corrections: string[];  // e.g. "end 3600 s clamped to 1843.2 s"
+  clips: ClipResult[];  // One per segment of a segment list
+  checksums: FileChecksum[];  // Sources first, then outputs
+  warnings: string[];  // Source properties the output could not keep, e.g. HDR mastering metadata
+}
+
+/** A segment of a segment list as transcoded */
+export interface ClipResult {
+  output_path: string;  // Numbered file, or the joined output
+  segment: TimeSegment;
+  loudness: LoudnessMeasurement | null;
+}
+
+/** Result of validate_segments for one file */
+export interface SegmentCheck {
+  input_path: string;
+  segment: TimeSegment | null;  // Segment that would be transcoded
+  corrections: string[];
+  error: string | null;  // Why the segment was rejected
+}
+
+export type CardLayout = "Avchd" | "Xdcam" | "P2" | "GoPro" | "Dji";
+
+/** One recording on a camera card; spanned files are listed in order */
+export interface CardClip {
+  name: string;
+  layout: CardLayout;
+  file_paths: string[];  // More than one: transcode with start_concat_transcode
+  metadata: MediaMetadata;  // Covers every file of the recording
+}
+
+export interface CardScan {
+  layouts: CardLayout[];
+  clips: CardClip[];
+  skipped: string[];  // Files that could not be read, with the reason
+}
+
 export interface FfmpegAvailability {
   ffmpeg: boolean;
   ffprobe: boolean;
@@ -72,11 +309,19 @@ export interface FfmpegAvailability {
 }
 
 // Batch transcoding types
-export interface BatchTranscodeRequest {
+export interface BatchTranscodeRequest extends TranscodeOptions {
   input_paths: string[];
   output_paths: string[];  // Full output paths for each input file
   preset: OutputPreset;
   segments?: (TimeSegment | null)[];  // Optional segments for each file
+  segment_lists?: (TimeSegment[] | null)[];  // Optional segment list for each file, used instead of its segment
+}
+
+// Joins the inputs, in order, into one output
+export interface ConcatTranscodeRequest extends TranscodeOptions {
+  input_paths: string[];
+  output_path: string;
+  preset: OutputPreset;
 }
 
 export interface BatchProgress {
@@ -107,6 +352,7 @@ export interface FileTask {
   originalFileName: string; // Original input file name for display
   segment: TimeSegment | null;  // null means full video
   preset?: OutputPreset;  // undefined = use global preset, set = custom preset for this file
+  error?: string;  // Reason the transcode failed, from the batch-transcode-error event
 }
 
 // Helper to get the full output path from a FileTask
@@ -170,6 +416,10 @@ export const PRESET_DISPLAY_NAMES: Record<OutputPreset, string> = {
   ProRes422Proxy: "ProRes 422 Proxy",
   DnxHRHQX: "DNxHR HQX",
   H264Crf18: "H.264 CRF 18",
+  WavPcm24: "WAV 24-bit 48 kHz",
+  Flac: "FLAC",
+  Aac: "AAC 320k",
+  Remux: "Remux (copy)",
 };
 
 // App Settings interface
@@ -198,8 +448,19 @@ export function estimateOutputSize(
   metadata: MediaMetadata,
   preset: OutputPreset
 ): { minMB: number; maxMB: number } {
+  if (isAudioPreset(preset)) {
+    const totalMB = PRESET_BITRATE[preset] * 1_000_000 * metadata.duration_sec / BITS_PER_BYTE / BYTES_PER_MB;
+    return { minMB: totalMB, maxMB: totalMB };
+  }
+
+  const video = metadata.video;
+  // Remuxing keeps the source size, which the metadata does not carry
+  if (!video || preset === "Remux") {
+    return { minMB: 0, maxMB: 0 };
+  }
+
   if (preset === "H264Crf18") {
-    const pixels = metadata.video.width * metadata.video.height;
+    const pixels = video.width * video.height;
     const resolutionFactor = pixels / BASELINE_PIXELS;
     const minSizePerMin = H264_MIN_SIZE_MB_PER_MIN * resolutionFactor;
     const maxSizePerMin = H264_MAX_SIZE_MB_PER_MIN * resolutionFactor;
@@ -211,7 +472,7 @@ export function estimateOutputSize(
   }
 
   const baseBitrate = PRESET_BITRATE[preset] * 1_000_000;
-  const pixels = metadata.video.width * metadata.video.height;
+  const pixels = video.width * video.height;
   const resolutionFactor = pixels / BASELINE_PIXELS;
   const adjustedBitrate = baseBitrate * resolutionFactor;
   const totalBits = adjustedBitrate * metadata.duration_sec;
@@ -251,3 +512,47 @@ export function formatSizeRange(minMB: number, maxMB: number): string {
   }
   return `~${minMB.toFixed(0)}-${maxMB.toFixed(0)} MB`;
 }
+
+// Verified offload types
+export type HashAlgorithm = "XxHash64" | "Md5" | "Sha1";
+
+export interface ChecksumOptions {
+  algorithm: HashAlgorithm;
+  manifest_path?: string;  // Shared manifest, e.g. per batch; omit for sidecars like "A001.mov.source.md5"
+}
+
+export interface FileChecksum {
+  path: string;
+  algorithm: HashAlgorithm;
+  hash: string;
+}
+
+export interface OffloadRequest {
+  source_paths: string[];  // Folders keep their name at each destination
+  destination_paths: string[];
+  hash?: HashAlgorithm;  // Defaults to XxHash64
+  then_transcode?: BatchTranscodeRequest;  // Inputs name source files, read from the first copy
+}
+
+export type OffloadPhase = "Copy" | "Verify";
+
+export interface OffloadProgress {
+  offload_id: string;
+  phase: OffloadPhase;
+  progress: TranscodeProgress;  // bitrate carries the throughput, e.g. "212.4 MB/s"
+}
+
+export interface OffloadedFile {
+  source_path: string;
+  destination_paths: string[];
+  size: number;
+  hash: string;
+}
+
+export interface OffloadResult {
+  offload_id: string;
+  hash: HashAlgorithm;
+  files: OffloadedFile[];
+  manifest_paths: string[];  // MHL manifest in each destination
+  batch_id: string | null;  // Batch started from the verified copies
+}